@@ -1,27 +1,264 @@
-const sep = 8;
-
-let memory_div = document.getElementById("memory");
 let registers_div = document.getElementById("registers");
 let hz_div = document.getElementById("hz");
 let rom_keys = document.getElementById("rom_keys");
 let rom_name = document.getElementById("rom_name");
+let keypad_div = document.getElementById("keypad");
+let error_p = document.getElementById("error");
+let debug_state = document.getElementById("debug_state");
+let breakpoints_pre = document.getElementById("breakpoints");
+let watchpoints_pre = document.getElementById("watchpoints");
+let movie_button = document.getElementById("movie_record");
+let stack_div = document.getElementById("stack");
 
-for (let i=0; i < 4096; i+=sep) {
-    memory_div.appendChild(document.createElement("div"));
-}
 for (let i=0; i < 16; i++) {
     registers_div.appendChild(document.createElement("div"));
+    stack_div.appendChild(document.createElement("div"));
+}
+
+// hex view of memory, 16 bytes a row. Only the rows in view exist, the
+// spacer around them gives the scrollbar the height of all 64 KiB.
+const HEX_ROWS = 16;
+const HEX_ROW_HEIGHT = 20; // px, see .hexdump in index.html
+let hexdump_div = document.getElementById("hexdump");
+let hexdump_rows = document.getElementById("hexdump_rows");
+let hex_start = 0;      // address of the first row in view
+let hex_editing = null; // address of the byte being edited
+let regions = [];       // [{start, end, name}], sent when a ROM loads
+
+function hex(n, digits) {
+    return n.toString(16).toUpperCase().padStart(digits, "0");
+}
+
+function region_at(addr) {
+    const region = regions.find((r) => r.start <= addr && addr <= r.end);
+    return region ? region.name : "";
+}
+
+for (let row=0; row < HEX_ROWS; row++) {
+    const row_div = document.createElement("div");
+    const label = document.createElement("span");
+    label.className = "addr";
+    row_div.appendChild(label);
+    for (let col=0; col < 16; col++) {
+        const cell = document.createElement("span");
+        cell.className = "byte";
+        cell.onclick = () => edit_byte(cell, hex_start + row * 16 + col);
+        row_div.appendChild(cell);
+    }
+    hexdump_rows.appendChild(row_div);
+}
+
+hexdump_div.onscroll = () => {
+    const row = Math.floor(hexdump_div.scrollTop / HEX_ROW_HEIGHT);
+    hexdump_rows.style.top = `${row * HEX_ROW_HEIGHT}px`;
+    hex_start = row * 16;
+    wasm_exports.memory_view(js_object({"start": hex_start, "len": HEX_ROWS * 16}));
+};
+
+function hex_goto() {
+    const addr = parseInt(document.getElementById("hex_goto").value, 16);
+    if (addr >= 0 && addr <= 0xFFFF) {
+        hexdump_div.scrollTop = Math.floor(addr / 16) * HEX_ROW_HEIGHT;
+    }
+}
+
+// type a new hex value, enter writes it and escape (or clicking away) cancels
+function edit_byte(cell, addr) {
+    if (hex_editing !== null) return;
+    hex_editing = addr;
+    cell.contentEditable = "true";
+    cell.focus();
+    document.getSelection().selectAllChildren(cell);
+
+    const done = () => {
+        cell.contentEditable = "false";
+        cell.onkeydown = null;
+        cell.onblur = null;
+        hex_editing = null;
+    };
+    cell.onkeydown = (e) => {
+        if (e.key === "Enter") {
+            e.preventDefault();
+            const value = parseInt(cell.innerText.trim(), 16);
+            if (value >= 0 && value <= 0xFF) {
+                error_p.innerText = "";
+                wasm_exports.poke_memory(js_object({"addr": addr, "value": value}));
+            }
+            done();
+        } else if (e.key === "Escape") {
+            done();
+        }
+        // keep the emulator's keyboard input out of it
+        e.stopPropagation();
+    };
+    cell.onblur = done;
+}
+
+// on-screen hex keypad, laid out like the COSMAC VIP
+const KEYPAD_LAYOUT = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+for (let key of KEYPAD_LAYOUT) {
+    let button = document.createElement("button");
+    button.innerText = key.toString(16).toUpperCase();
+    button.onpointerdown = () => set_key(key, true);
+    button.onpointerup = () => set_key(key, false);
+    button.onpointerleave = () => set_key(key, false);
+    keypad_div.appendChild(button);
+}
+
+// sound timer output, the wasm side sends a looping WAV for the current tone
+let audio_ctx = null;
+let gain_node = null;
+let tone_buffer = null;
+let tone_source = null;
+
+function audio_context() {
+    if (!audio_ctx) audio_ctx = new AudioContext();
+    if (audio_ctx.state === "suspended") audio_ctx.resume();
+    return audio_ctx;
+}
+
+function tone_gain() {
+    if (!gain_node) {
+        gain_node = audio_context().createGain();
+        gain_node.connect(audio_context().destination);
+    }
+    return gain_node;
+}
+
+function tone_stop() {
+    if (tone_source) {
+        tone_source.stop();
+        tone_source = null;
+    }
 }
 
 miniquad_add_plugin({register_plugin: function (importObject) {
+    // the start address (u16 big endian), the bytes in view, then their heat
     importObject.env.draw_memory = function (js_object) {
-        const memory = consume_js_object(js_object);
-        for (let i=0; i < 4096; i+=sep) {
-            let b = 0.1;
-            for (let j=0; j < sep; j++) {
-                b += memory[i + j]/255.0
+        const data = consume_js_object(js_object);
+        const start = (data[0] << 8) | data[1];
+        const len = (data.length - 2) / 2;
+        for (let row=0; row < HEX_ROWS; row++) {
+            const row_div = hexdump_rows.children[row];
+            const row_addr = start + row * 16;
+            row_div.children[0].innerText = `${hex(row_addr, 4)} ${region_at(row_addr)}`;
+            for (let col=0; col < 16; col++) {
+                const i = row * 16 + col;
+                const cell = row_div.children[col + 1];
+                if (row_addr + col === hex_editing) continue;
+                if (i >= len) {
+                    cell.innerText = "  ";
+                    continue;
+                }
+                const heat = data[2 + len + i] / 255.0;
+                cell.innerText = hex(data[2 + i], 2);
+                cell.style.backgroundColor = `rgba(255, 102, 201, ${heat})`;
+                cell.style.color = heat > 0.5 ? "black" : "";
             }
-            memory_div.children[i/sep].style.backgroundColor = `rgba(255, 102, 201, ${b})`;
+        }
+    }
+
+    // one "start end name" line per region, addresses in hex
+    importObject.env.memory_regions = function (js_object) {
+        regions = consume_js_object(js_object).split("\n").map((line) => {
+            const [start, end, ...name] = line.split(" ");
+            return {"start": parseInt(start, 16), "end": parseInt(end, 16), "name": name.join(" ")};
+        });
+    }
+
+    importObject.env.report_error = function (js_object) {
+        error_p.innerText = consume_js_object(js_object);
+    }
+
+    importObject.env.download_state = function (js_object) {
+        const state = consume_js_object(js_object);
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([state]));
+        link.download = `${document.getElementById("rom").value}.state`;
+        link.click();
+        URL.revokeObjectURL(link.href);
+    }
+
+    importObject.env.download_movie = function (js_object) {
+        const movie = consume_js_object(js_object);
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([movie]));
+        link.download = "chirp.movie";
+        link.click();
+        URL.revokeObjectURL(link.href);
+        movie_button.innerText = "Record Movie";
+    }
+
+    importObject.env.load_display = function () {
+        const settings = localStorage.getItem(DISPLAY_KEY) || "";
+        show_display(settings);
+        return js_object(settings);
+    }
+
+    importObject.env.save_display = function (js_object) {
+        const settings = consume_js_object(js_object);
+        localStorage.setItem(DISPLAY_KEY, settings);
+        show_display(settings);
+    }
+
+    importObject.env.tone_load = function (js_object) {
+        const wav = consume_js_object(js_object);
+        tone_stop();
+        tone_buffer = audio_context().decodeAudioData(wav.slice().buffer);
+    }
+
+    importObject.env.tone_play = function (js_object) {
+        const volume = consume_js_object(js_object).volume;
+        tone_gain().gain.value = volume;
+        if (tone_source) return;
+
+        const buffer = tone_buffer;
+        buffer.then((decoded) => {
+            if (buffer !== tone_buffer || tone_source) return;
+            tone_source = audio_context().createBufferSource();
+            tone_source.buffer = decoded;
+            tone_source.loop = true;
+            tone_source.connect(tone_gain());
+            tone_source.start();
+        });
+    }
+
+    importObject.env.tone_stop = function () {
+        tone_stop();
+    }
+
+    importObject.env.debug_status = function (js_object) {
+        const status = consume_js_object(js_object);
+        debug_state.innerText = status.state;
+        breakpoints_pre.innerText = status.breakpoints;
+        watchpoints_pre.innerText = status.watchpoints;
+    }
+
+    importObject.env.draw_cpu = function (js_object) {
+        const cpu = consume_js_object(js_object);
+        document.getElementById("cpu_pc").innerText = hex(cpu.pc, 4);
+        document.getElementById("cpu_i").innerText = hex(cpu.i, 4);
+        document.getElementById("cpu_dt").innerText = hex(cpu.delay, 2);
+        document.getElementById("cpu_st").innerText = hex(cpu.sound, 2);
+        document.getElementById("cpu_sp").innerText = cpu.sp;
+        document.getElementById("cpu_opcode").innerText = hex(cpu.opcode, 4);
+        document.getElementById("cpu_octo").innerText = cpu.octo;
+        document.getElementById("cpu_classic").innerText = cpu.classic;
+
+        let state = "running";
+        if (cpu.halted) {
+            state = "halted";
+        } else if (cpu.waiting >= 0) {
+            state = `waiting for a key to store in V${hex(cpu.waiting, 1)}`;
+        }
+        document.getElementById("cpu_state").innerText = state;
+
+        // slots below the stack pointer hold return addresses, the last is the top
+        const stack = cpu.stack.split(" ");
+        for (let i=0; i < 16; i++) {
+            const slot = stack_div.children[i];
+            slot.innerText = stack[i];
+            slot.className = i < cpu.sp ? (i === cpu.sp - 1 ? "used top" : "used") : "";
         }
     }
 
@@ -57,14 +294,179 @@ update_keys("brix");
 function load_rom() {
     let rom_name = document.getElementById("rom").value;
     update_keys(rom_name);
+    error_p.innerText = "";
     wasm_exports.load_rom(js_object(rom_name));
 }
 
+// a .ch8 file from the file picker or dropped on the screen
+function load_rom_file(file) {
+    if (!file) return;
+    file.arrayBuffer().then((buffer) => {
+        rom_name.innerText = file.name;
+        rom_keys.innerHTML = "";
+        error_p.innerText = "";
+        wasm_exports.load_rom_bytes(js_object(new Uint8Array(buffer)));
+    });
+}
+
+function load_rom_input(input) {
+    load_rom_file(input.files[0]);
+    input.value = "";
+}
+
+const screen_div = document.getElementById("screen");
+screen_div.ondragover = (e) => {
+    e.preventDefault();
+    screen_div.classList.add("dragging");
+};
+screen_div.ondragleave = () => screen_div.classList.remove("dragging");
+screen_div.ondrop = (e) => {
+    e.preventDefault();
+    screen_div.classList.remove("dragging");
+    load_rom_file(e.dataTransfer.files[0]);
+};
+
 function update_hz(hz) {
     hz_div.innerText = `${hz}Hz`;
     wasm_exports.update_hz(js_object({"new_hz": hz}));
 }
 
+function set_speed(speed) {
+    wasm_exports.set_speed(js_object({"speed": parseFloat(speed)}));
+}
+
+function set_key(key, down) {
+    wasm_exports.set_key(js_object({"key": key, "down": down ? 1 : 0}));
+}
+
+function set_quirks(profile) {
+    wasm_exports.set_quirks(js_object(profile));
+}
+
+function set_timing(timing) {
+    wasm_exports.set_timing(js_object(timing));
+}
+
+function save_state() {
+    wasm_exports.export_state();
+}
+
+function load_state(input) {
+    const file = input.files[0];
+    if (!file) return;
+    file.arrayBuffer().then((buffer) => {
+        error_p.innerText = "";
+        wasm_exports.import_state(js_object(new Uint8Array(buffer)));
+        input.value = "";
+    });
+}
+
+// recording restarts the ROM, stopping downloads the movie
+function movie_record() {
+    if (movie_button.innerText === "Record Movie") {
+        movie_button.innerText = "Stop Recording";
+    }
+    error_p.innerText = "";
+    wasm_exports.movie_record();
+}
+
+function movie_play(input) {
+    const file = input.files[0];
+    if (!file) return;
+    file.arrayBuffer().then((buffer) => {
+        error_p.innerText = "";
+        wasm_exports.movie_play(js_object(new Uint8Array(buffer)));
+        input.value = "";
+    });
+}
+
+// display settings are kept in local storage as the wasm side's `key=value` lines
+const DISPLAY_KEY = "chirp.display";
+
+function show_display(settings) {
+    for (const line of settings.split("\n")) {
+        const [key, value] = line.split("=");
+        if (key === "fg" || key === "bg") {
+            document.getElementById(key).value = `#${value.toLowerCase()}`;
+        } else if (key === "pixels") {
+            document.getElementById("pixels").value = value;
+        }
+    }
+}
+
+function set_palette(name) {
+    wasm_exports.set_palette(js_object(name));
+}
+
+function set_colours() {
+    const fg = document.getElementById("fg").value.slice(1);
+    const bg = document.getElementById("bg").value.slice(1);
+    wasm_exports.set_colours(js_object({"fg": fg, "bg": bg}));
+}
+
+function set_pixels(style) {
+    wasm_exports.set_pixels(js_object(style));
+}
+
+function toggle_mute() {
+    wasm_exports.toggle_mute();
+}
+
+function set_volume(volume) {
+    wasm_exports.set_volume(js_object({"volume": volume / 100}));
+}
+
+function set_pitch(pitch) {
+    wasm_exports.set_pitch(js_object({"pitch": Number(pitch)}));
+}
+
+function debug_pause() {
+    wasm_exports.debug_pause();
+}
+
+function debug_step() {
+    wasm_exports.debug_step();
+}
+
+function debug_step_over() {
+    wasm_exports.debug_step_over();
+}
+
+function debug_step_out() {
+    wasm_exports.debug_step_out();
+}
+
+function add_breakpoint() {
+    const addr = document.getElementById("bp_addr").value;
+    const condition = document.getElementById("bp_condition").value;
+    wasm_exports.add_breakpoint(js_object({"addr": addr, "condition": condition}));
+}
+
+function remove_breakpoint() {
+    wasm_exports.remove_breakpoint(js_object(document.getElementById("bp_addr").value));
+}
+
+function load_symbols(input) {
+    const file = input.files[0];
+    if (!file) return;
+    file.text().then((text) => {
+        wasm_exports.import_symbols(js_object(text));
+        input.value = "";
+    });
+}
+
+function add_watchpoint() {
+    const start = document.getElementById("watch_start").value;
+    const end = document.getElementById("watch_end").value;
+    wasm_exports.add_watchpoint(js_object({"start": start, "end": end}));
+}
+
+function remove_watchpoint() {
+    const start = document.getElementById("watch_start").value;
+    const end = document.getElementById("watch_end").value;
+    wasm_exports.remove_watchpoint(js_object({"start": start, "end": end}));
+}
+
 function toggle_bloom() {
     wasm_exports.toggle_bloom();
 }
@@ -0,0 +1,662 @@
+// Assembler for CHIP-8, SCHIP and XO-CHIP programs.
+//
+// Source uses the classic mnemonics that `disasm` prints with
+// `Syntax::Classic`, one statement per line and `;` comments:
+//
+//   speed equ 2             ; constant
+//   main:                   ; label
+//       LD I, ball
+//       DRW V0, V1, 4
+//       ADD V0, speed
+//       JP main
+//   ball:
+//       sprite .XX.....     ; sprite literal, 8 or 16 pixels
+//       sprite XXXX....
+//       db 0x60, %01100000  ; bytes
+//       dw 0x1234, ball     ; big endian words
+//       include "font.asm"  ; relative to the including file
+//       org 0x300           ; pad with zeros up to an address
+//
+// Numbers are decimal, `0x`/`$` hex or `0b`/`%` binary, and operands can add
+// or subtract labels and constants. Output starts at 0x200, ready for
+// `Chip8::load(0x200, program.bytes)`.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::instruction::Instruction;
+
+pub const ORIGIN: u16 = 0x200;
+
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+
+// includes deeper than this are assumed to be a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize, // 1-based
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>, // labels and constants
+}
+
+impl Program {
+    // `0x0200 main` per line, sorted by name; read back with `parse_symbols`
+    pub fn symbol_map(&self) -> String {
+        self.symbols
+            .iter()
+            .map(|(name, addr)| format!("{:#06x} {}\n", addr, name))
+            .collect()
+    }
+}
+
+pub fn parse_symbols(map: &str) -> BTreeMap<String, u16> {
+    map.lines()
+        .filter_map(|line| {
+            let (addr, name) = line.trim().split_once(' ')?;
+            let addr = u16::from_str_radix(addr.strip_prefix("0x")?, 16).ok()?;
+            Some((name.trim().to_owned(), addr))
+        })
+        .collect()
+}
+
+// includes are read relative to the working directory
+pub fn assemble(source: &str) -> Result<Program, Vec<AsmError>> {
+    Assembler::new().run("<source>", source, Path::new("."))
+}
+
+pub fn assemble_file(path: &Path) -> Result<Program, Vec<AsmError>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        vec![AsmError {
+            file: path.display().to_string(),
+            line: 0,
+            message: e.to_string(),
+        }]
+    })?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Assembler::new().run(&path.display().to_string(), &source, dir)
+}
+
+#[derive(Clone, Debug)]
+struct Loc {
+    file: usize, // index into `Assembler::files`
+    line: usize,
+}
+
+enum Item {
+    Instruction { mnemonic: String, args: Vec<String> },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Raw(Vec<u8>),
+    Org(u16),
+}
+
+struct Assembler {
+    files: Vec<String>,
+    items: Vec<(Loc, u16, Item)>, // with the address each item starts at
+    symbols: BTreeMap<String, u16>,
+    errors: Vec<AsmError>,
+    addr: u32,
+    full: bool, // ran past the end of memory, stop reading
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            items: Vec::new(),
+            symbols: BTreeMap::new(),
+            errors: Vec::new(),
+            addr: ORIGIN as u32,
+            full: false,
+        }
+    }
+
+    fn run(mut self, name: &str, source: &str, dir: &Path) -> Result<Program, Vec<AsmError>> {
+        self.read(name, source, dir, 0);
+        // the layout can't be trusted after an error
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        let bytes = self.encode();
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        Ok(Program {
+            origin: ORIGIN,
+            bytes,
+            symbols: self.symbols,
+        })
+    }
+
+    fn error(&mut self, loc: &Loc, message: impl Into<String>) {
+        self.errors.push(AsmError {
+            file: self.files[loc.file].clone(),
+            line: loc.line,
+            message: message.into(),
+        });
+    }
+
+    // first pass: define labels and lay out every item
+    fn read(&mut self, name: &str, source: &str, dir: &Path, depth: usize) {
+        self.files.push(name.to_owned());
+        let file = self.files.len() - 1;
+
+        for (n, line) in source.lines().enumerate() {
+            if self.full {
+                return;
+            }
+            let loc = Loc { file, line: n + 1 };
+            let mut line = line.split(';').next().unwrap().trim();
+
+            if let Some((label, rest)) = line.split_once(':') {
+                let label = label.trim();
+                if is_identifier(label) {
+                    self.define(&loc, label, self.addr as u16);
+                    line = rest.trim();
+                }
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (word, rest) = match line.split_once(char::is_whitespace) {
+                Some((word, rest)) => (word, rest.trim()),
+                None => (line, ""),
+            };
+            let args = || -> Vec<String> {
+                rest.split(',')
+                    .map(|a| a.trim().to_owned())
+                    .filter(|a| !a.is_empty())
+                    .collect()
+            };
+
+            let item = match word.to_ascii_lowercase().as_str() {
+                "db" => Item::Bytes(args()),
+                "dw" => Item::Words(args()),
+                "sprite" => match sprite(rest) {
+                    Ok(bytes) => Item::Raw(bytes),
+                    Err(e) => {
+                        self.error(&loc, e);
+                        continue;
+                    }
+                },
+                "org" => match self.eval(rest) {
+                    Ok(addr) if addr >= self.addr as i64 && addr <= 0xFFFF => {
+                        Item::Org(addr as u16)
+                    }
+                    Ok(addr) => {
+                        self.error(&loc, format!("org {:#x} is behind {:#x}", addr, self.addr));
+                        continue;
+                    }
+                    Err(e) => {
+                        self.error(&loc, e);
+                        continue;
+                    }
+                },
+                "include" => {
+                    self.include(&loc, rest, dir, depth);
+                    continue;
+                }
+                _ => {
+                    // `name equ value`
+                    if let Some(value) = rest
+                        .split_once(char::is_whitespace)
+                        .filter(|(kw, _)| kw.eq_ignore_ascii_case("equ"))
+                        .map(|(_, value)| value.trim())
+                    {
+                        match self.eval(value) {
+                            Ok(v) if (0..=0xFFFF).contains(&v) => self.define(&loc, word, v as u16),
+                            Ok(v) => self.error(&loc, format!("{} is out of range", v)),
+                            Err(e) => self.error(&loc, e),
+                        }
+                        continue;
+                    }
+                    Item::Instruction {
+                        mnemonic: word.to_ascii_uppercase(),
+                        args: args(),
+                    }
+                }
+            };
+
+            let size = match &item {
+                Item::Instruction { mnemonic, args } => {
+                    let long = mnemonic == "LD"
+                        && args.len() == 2
+                        && args[1].to_ascii_uppercase().starts_with("LONG ");
+                    if long {
+                        4
+                    } else {
+                        2
+                    }
+                }
+                Item::Bytes(args) => args.len() as u32,
+                Item::Words(args) => 2 * args.len() as u32,
+                Item::Raw(bytes) => bytes.len() as u32,
+                Item::Org(addr) => *addr as u32 - self.addr,
+            };
+            if self.addr + size > 0x10000 {
+                self.error(&loc, "program doesn't fit in memory");
+                self.full = true;
+                return;
+            }
+            self.items.push((loc.clone(), self.addr as u16, item));
+            self.addr += size;
+        }
+    }
+
+    fn include(&mut self, loc: &Loc, arg: &str, dir: &Path, depth: usize) {
+        let Some(path) = arg.strip_prefix('"').and_then(|a| a.strip_suffix('"')) else {
+            return self.error(loc, "include needs a quoted path");
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            return self.error(loc, "includes nested too deeply");
+        }
+        let path: PathBuf = dir.join(path);
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                let dir = path.parent().unwrap_or(Path::new(".")).to_owned();
+                self.read(&path.display().to_string(), &source, &dir, depth + 1);
+            }
+            Err(e) => self.error(loc, format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    fn define(&mut self, loc: &Loc, name: &str, value: u16) {
+        if !is_identifier(name) {
+            return self.error(loc, format!("bad name {}", name));
+        }
+        if self.symbols.insert(name.to_owned(), value).is_some() {
+            self.error(loc, format!("{} is already defined", name));
+        }
+    }
+
+    // second pass: every label is known now
+    fn encode(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (loc, addr, item) in std::mem::take(&mut self.items) {
+            // earlier errors can leave gaps, keep later addresses right
+            out.resize((addr - ORIGIN) as usize, 0);
+            let result = match &item {
+                Item::Instruction { mnemonic, args } => self.instruction(mnemonic, args),
+                Item::Bytes(args) => self.data(args, false),
+                Item::Words(args) => self.data(args, true),
+                Item::Raw(bytes) => Ok(bytes.clone()),
+                Item::Org(_) => Ok(Vec::new()),
+            };
+            match result {
+                Ok(bytes) => out.extend_from_slice(&bytes),
+                Err(e) => self.error(&loc, e),
+            }
+        }
+        out
+    }
+
+    fn data(&self, args: &[String], words: bool) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        for arg in args {
+            if words {
+                out.extend_from_slice(&self.value(arg, 0xFFFF)?.to_be_bytes());
+            } else {
+                out.push(self.value(arg, 0xFF)? as u8);
+            }
+        }
+        Ok(out)
+    }
+
+    fn eval(&self, expr: &str) -> Result<i64, String> {
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return Err("missing value".to_owned());
+        }
+
+        // terms joined by + and -, a leading - negates
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        for c in expr.chars() {
+            if (c == '+' || c == '-') && !term.trim().is_empty() {
+                total += sign * self.term(term.trim())?;
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            } else if c == '-' {
+                sign = -sign;
+            } else if c != '+' {
+                term.push(c);
+            }
+        }
+        if term.trim().is_empty() {
+            return Err(format!("bad expression {}", expr));
+        }
+        Ok(total + sign * self.term(term.trim())?)
+    }
+
+    fn term(&self, term: &str) -> Result<i64, String> {
+        let lower = term.to_ascii_lowercase();
+        let number = if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('$')) {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = lower.strip_prefix("0b").or(lower.strip_prefix('%')) {
+            i64::from_str_radix(bin, 2).ok()
+        } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+            term.parse().ok()
+        } else {
+            return match self.symbols.get(term) {
+                Some(&v) => Ok(v as i64),
+                None => Err(format!("unknown symbol {}", term)),
+            };
+        };
+        number.ok_or(format!("bad number {}", term))
+    }
+
+    // a value that must fit in `max`, negative bytes wrap like ADD VX, -1
+    fn value(&self, expr: &str, max: u16) -> Result<u16, String> {
+        let v = self.eval(expr)?;
+        if max == 0xFF && (-128..0).contains(&v) {
+            return Ok((v + 256) as u16);
+        }
+        if v < 0 || v > max as i64 {
+            return Err(format!("{} doesn't fit in {:#x}", expr, max));
+        }
+        Ok(v as u16)
+    }
+
+    fn instruction(&self, mnemonic: &str, args: &[String]) -> Result<Vec<u8>, String> {
+        use Instruction::*;
+        use Operand::{Dt, Expr, Hf, IndexedI, Long, St, B, F, I, K, R, V};
+        let ops: Vec<Operand> = args.iter().map(|a| Operand::parse(a)).collect();
+        let nnn = |e: &str| self.value(e, 0xFFF);
+        let nn = |e: &str| self.value(e, 0xFF).map(|v| v as u8);
+        let n = |e: &str| self.value(e, 0xF).map(|v| v as u8);
+
+        let instruction = match (mnemonic, ops.as_slice()) {
+            ("CLS", []) => Clear,
+            ("RET", []) => Return,
+            ("SCD", [Expr(e)]) => ScrollDown { n: n(e)? },
+            ("SCU", [Expr(e)]) => ScrollUp { n: n(e)? },
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LoRes,
+            ("HIGH", []) => HiRes,
+            ("JP", [Expr(e)]) => Jump { nnn: nnn(e)? },
+            ("JP", [V(0), Expr(e)]) => JumpOffset { nnn: nnn(e)? },
+            ("CALL", [Expr(e)]) => Call { nnn: nnn(e)? },
+            ("SE", [V(x), Expr(e)]) => SkipEqImm { x: *x, nn: nn(e)? },
+            ("SNE", [V(x), Expr(e)]) => SkipNeImm { x: *x, nn: nn(e)? },
+            ("SE", [V(x), V(y)]) => SkipEq { x: *x, y: *y },
+            ("SAVE", [V(x), V(y)]) => SaveRange { x: *x, y: *y },
+            ("LOAD", [V(x), V(y)]) => LoadRange { x: *x, y: *y },
+            ("LD", [V(x), V(y)]) => Set { x: *x, y: *y },
+            ("LD", [V(x), Dt]) => GetDelay { x: *x },
+            ("LD", [V(x), K]) => WaitKey { x: *x },
+            ("LD", [V(x), IndexedI]) => Load { x: *x },
+            ("LD", [V(x), R]) => LoadFlags { x: *x },
+            ("LD", [V(x), Expr(e)]) => SetImm { x: *x, nn: nn(e)? },
+            ("LD", [I, Long(e)]) => SetILong {
+                nnnn: self.value(e, 0xFFFF)?,
+            },
+            ("LD", [I, Expr(e)]) => SetI { nnn: nnn(e)? },
+            ("LD", [Dt, V(x)]) => SetDelay { x: *x },
+            ("LD", [St, V(x)]) => SetSound { x: *x },
+            ("LD", [F, V(x)]) => Font { x: *x },
+            ("LD", [Hf, V(x)]) => BigFont { x: *x },
+            ("LD", [B, V(x)]) => Bcd { x: *x },
+            ("LD", [IndexedI, V(x)]) => Store { x: *x },
+            ("LD", [R, V(x)]) => SaveFlags { x: *x },
+            ("ADD", [V(x), V(y)]) => Add { x: *x, y: *y },
+            ("ADD", [V(x), Expr(e)]) => AddImm { x: *x, nn: nn(e)? },
+            ("ADD", [I, V(x)]) => AddI { x: *x },
+            ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Sub { x: *x, y: *y },
+            ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => SubReverse { x: *x, y: *y },
+            ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+            ("SNE", [V(x), V(y)]) => SkipNe { x: *x, y: *y },
+            ("RND", [V(x), Expr(e)]) => Random { x: *x, nn: nn(e)? },
+            ("DRW", [V(x), V(y), Expr(e)]) => Draw {
+                x: *x,
+                y: *y,
+                n: n(e)?,
+            },
+            ("SKP", [V(x)]) => SkipKey { x: *x },
+            ("SKNP", [V(x)]) => SkipNotKey { x: *x },
+            ("PLANE", [Expr(e)]) => Plane { n: n(e)? },
+            ("AUDIO", []) => Audio,
+            ("PITCH", [V(x)]) => Pitch { x: *x },
+            _ if !MNEMONICS.contains(&mnemonic) => {
+                return Err(format!("unknown instruction {}", mnemonic))
+            }
+            _ => {
+                return Err(format!(
+                    "bad operands for {}: {}",
+                    mnemonic,
+                    args.join(", ")
+                ))
+            }
+        };
+        let mut bytes = instruction.encode().to_be_bytes().to_vec();
+        if let SetILong { nnnn } = instruction {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+enum Operand<'a> {
+    V(u8),
+    I,
+    IndexedI, // [I]
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(&'a str),
+    Expr(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    fn parse(arg: &'a str) -> Self {
+        let upper = arg.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Self::I,
+            "[I]" => Self::IndexedI,
+            "DT" => Self::Dt,
+            "ST" => Self::St,
+            "K" => Self::K,
+            "F" => Self::F,
+            "HF" => Self::Hf,
+            "B" => Self::B,
+            "R" => Self::R,
+            _ if upper.starts_with("LONG ") => Self::Long(arg[5..].trim()),
+            _ => match upper.strip_prefix('V').map(|x| u8::from_str_radix(x, 16)) {
+                Some(Ok(x)) if upper.len() == 2 => Self::V(x),
+                _ => Self::Expr(arg),
+            },
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// `XX..XX..` style, 8 or 16 pixels wide
+fn sprite(row: &str) -> Result<Vec<u8>, String> {
+    let row = row.trim();
+    if row.len() != 8 && row.len() != 16 {
+        return Err(format!("sprite rows are 8 or 16 pixels, got {}", row.len()));
+    }
+    let mut bits: u16 = 0;
+    for c in row.chars() {
+        bits = bits << 1
+            | match c {
+                'X' | 'x' | '#' | '1' | '*' => 1,
+                '.' | '_' | '0' => 0,
+                _ => return Err(format!("bad sprite pixel {:?}", c)),
+            };
+    }
+    Ok(if row.len() == 8 {
+        vec![bits as u8]
+    } else {
+        bits.to_be_bytes().to_vec()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    // the line numbers of every error
+    fn error_lines(source: &str) -> Vec<usize> {
+        let errors = assemble(source).unwrap_err();
+        errors.iter().map(|e| e.line).collect()
+    }
+
+    #[test]
+    fn labels() {
+        let program = assemble(
+            "main:
+                 CALL sub
+             loop: JP loop
+             sub:
+                 LD I, main + 2
+                 RET",
+        )
+        .unwrap();
+        assert_eq!(
+            program.bytes,
+            [0x22, 0x04, 0x12, 0x02, 0xA2, 0x02, 0x00, 0xEE]
+        );
+        assert_eq!(program.symbols["loop"], 0x202);
+        assert_eq!(program.symbols["sub"], 0x204);
+        assert_eq!(parse_symbols(&program.symbol_map()), program.symbols);
+    }
+
+    #[test]
+    fn equ() {
+        let source = "speed equ 2
+                      top EQU $10 - speed
+                      ADD V0, speed
+                      LD V1, top
+                      ADD V2, -1";
+        assert_eq!(bytes(source), [0x70, 0x02, 0x61, 0x0E, 0x72, 0xFF]);
+    }
+
+    #[test]
+    fn data() {
+        let source = "db 1, 0x02, %11, 0b100, $FF
+                      dw 0x1234, end
+                      end:";
+        assert_eq!(
+            bytes(source),
+            [0x01, 0x02, 0x03, 0x04, 0xFF, 0x12, 0x34, 0x02, 0x09]
+        );
+    }
+
+    #[test]
+    fn sprites() {
+        let source = "sprite .XX.....
+                      sprite x..x....x......x
+                      LD I, LONG 0xBEEF";
+        assert_eq!(bytes(source), [0x60, 0x90, 0x81, 0xF0, 0x00, 0xBE, 0xEF]);
+        assert_eq!(error_lines("sprite XX\nsprite .......?"), [1, 2]);
+    }
+
+    #[test]
+    fn org() {
+        let program = assemble("CLS\norg 0x206\nhere: RET").unwrap();
+        assert_eq!(program.bytes, [0x00, 0xE0, 0, 0, 0, 0, 0x00, 0xEE]);
+        assert_eq!(program.symbols["here"], 0x206);
+        assert_eq!(error_lines("CLS\nCLS\norg 0x201"), [3]);
+    }
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("chirp-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("main.asm"),
+            "CALL draw\ninclude \"lib/draw.asm\"\nRET\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib/draw.asm"),
+            "draw:\n  CLS\n  include \"ret.asm\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/ret.asm"), "  RET\n  bad\n").unwrap();
+
+        let errors = assemble_file(&dir.join("main.asm")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].file.ends_with("ret.asm"), "{}", errors[0]);
+        assert_eq!(errors[0].line, 2);
+
+        fs::write(dir.join("lib/ret.asm"), "  RET\n").unwrap();
+        let program = assemble_file(&dir.join("main.asm")).unwrap();
+        assert_eq!(
+            program.bytes,
+            [0x22, 0x02, 0x00, 0xE0, 0x00, 0xEE, 0x00, 0xEE]
+        );
+
+        // a file that includes itself
+        fs::write(dir.join("loop.asm"), "include \"loop.asm\"\n").unwrap();
+        let errors = assemble_file(&dir.join("loop.asm")).unwrap_err();
+        assert!(errors[0].message.contains("nested"), "{}", errors[0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let source = "CLS
+                      LD V0, 0x100
+                      JP nowhere
+                      ; comment
+                      FOO V1
+                      ADD V1, V2, V3";
+        assert_eq!(error_lines(source), [2, 3, 5, 6]);
+        assert_eq!(error_lines("twice: CLS\ntwice: RET"), [2]);
+    }
+
+    #[test]
+    fn overflow() {
+        // fills memory exactly, anything more doesn't fit
+        let fill = "org 0xFFFE\nRET\n";
+        assert_eq!(bytes(fill).len(), 0x10000 - 0x200);
+        assert_eq!(error_lines(&format!("{}CLS\nRET\nCLS", fill)), [3]);
+        assert_eq!(error_lines("org 0xFFFF\nLD I, LONG 0\nCLS"), [2]);
+        let big = format!("db {}\nCLS", vec!["0"; 0x10000].join(","));
+        assert_eq!(error_lines(&big), [1]);
+    }
+}
@@ -0,0 +1,136 @@
+// Tone generation for the sound timer.
+//
+// The core doesn't own an audio device. `Beeper` decides what the machine
+// should sound like right now as a `Waveform`, and frontends turn that into a
+// looping sound with `Waveform::wav`.
+use crate::chip8::Chip8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beeper {
+    pub pitch: f32,  // frequency of the classic beep in Hz
+    pub volume: f32, // 0.0-1.0
+    pub muted: bool,
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Self {
+            pitch: 440.0,
+            volume: 0.25,
+            muted: false,
+        }
+    }
+
+    // should a tone be playing
+    pub fn active(&self, chippy: &Chip8) -> bool {
+        !self.muted && self.volume > 0.0 && chippy.sound_timer > 0
+    }
+
+    // XO-CHIP ROMs that set an audio pattern or pitch play that pattern,
+    // everything else gets a square wave at `pitch`
+    pub fn waveform(&self, chippy: &Chip8) -> Waveform {
+        if chippy.audio_pattern != Chip8::DEFAULT_AUDIO_PATTERN
+            || chippy.pitch != Chip8::DEFAULT_PITCH
+        {
+            Waveform::Pattern {
+                pattern: chippy.audio_pattern,
+                pitch: chippy.pitch,
+            }
+        } else {
+            Waveform::Square {
+                pitch: self.pitch.max(1.0).round() as u32,
+            }
+        }
+    }
+
+    pub fn volume_up(&mut self) {
+        self.volume = (self.volume + 0.05).min(1.0);
+    }
+
+    pub fn volume_down(&mut self) {
+        self.volume = (self.volume - 0.05).max(0.0);
+    }
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Waveform {
+    Square { pitch: u32 },                    // Hz
+    Pattern { pattern: [u8; 16], pitch: u8 }, // XO-CHIP 1-bit pattern, 128 bits
+}
+
+impl Waveform {
+    pub const SAMPLE_RATE: u32 = 44100;
+
+    // shortest loop length, so repeated loops don't click
+    const MIN_SAMPLES: usize = (Self::SAMPLE_RATE / 10) as usize;
+
+    // XO-CHIP pattern playback rate in bits per second
+    pub fn pattern_rate(pitch: u8) -> f32 {
+        4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+    }
+
+    // one loop of the waveform as 8-bit unsigned samples at `SAMPLE_RATE`,
+    // always a whole number of periods
+    pub fn samples(&self) -> Vec<u8> {
+        const LOW: u8 = 0x00;
+        const HIGH: u8 = 0xFF;
+        let rate = Self::SAMPLE_RATE as f32;
+
+        let period: Vec<u8> = match *self {
+            Self::Square { pitch } => {
+                let len = ((rate / pitch.max(1) as f32).round() as usize).max(2);
+                (0..len)
+                    .map(|i| if i < len / 2 { HIGH } else { LOW })
+                    .collect()
+            }
+            Self::Pattern { pattern, pitch } => {
+                let bits_per_sample = Self::pattern_rate(pitch) / rate;
+                let len = ((128.0 / bits_per_sample).round() as usize).max(1);
+                (0..len)
+                    .map(|i| {
+                        let bit = ((i as f32 * bits_per_sample) as usize) % 128;
+                        if (pattern[bit / 8] >> (7 - bit % 8)) & 1 != 0 {
+                            HIGH
+                        } else {
+                            LOW
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        let repeats = Self::MIN_SAMPLES.div_ceil(period.len());
+        period.repeat(repeats)
+    }
+
+    // one loop of the waveform as a mono 8-bit PCM WAV file
+    pub fn wav(&self) -> Vec<u8> {
+        let samples = self.samples();
+        let data_len = samples.len() as u32;
+
+        let mut wav = Vec::with_capacity(44 + samples.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes()); // chunk size
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&Self::SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&Self::SAMPLE_RATE.to_le_bytes()); // byte rate
+        wav.extend_from_slice(&1u16.to_le_bytes()); // block align
+        wav.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.extend_from_slice(&samples);
+        wav
+    }
+}
@@ -1,34 +1,76 @@
-use crate::{display::Display, keypad::KeyPad, memory::Memory, stack::Stack};
+use std::ops::RangeInclusive;
+
+use crate::{
+    display::Display,
+    error::{EmulatorError, ErrorPolicy, RomError},
+    instruction::{decode, Instruction},
+    keypad::KeyPad,
+    memory::Memory,
+    quirks::Quirks,
+    rng::Rng,
+    stack::Stack,
+    timing::Timing,
+    trace::{TraceEntry, Tracer},
+};
 
 pub struct Chip8 {
-    hz: i32,   // assumed frequency of cpu
-    tick: i32, // current tick
-
-    pub i: u16,           // index register
-    pub pc: u16,          // program counter
-    pub delay_timer: u8,  // delay timer
-    pub sound_timer: u8,  // sound timer
-    pub v: [u8; 16],      // v registers 0x0-0xE
-    pub keypad: KeyPad,   // keypad
-    pub display: Display, // display buffer
-    pub stack: Stack,     // 16 frame stack
-    pub memory: Memory,   /* Memory Map
-                           0x000-0x1FF - Chip 8 interpreter
-                           0x00-0x50 - Used for the built in 4x5 pixel font set (0-F)
-                           0x200-0xFFF - Program ROM and work RAM
-                          */
+    pub(crate) hz: i32,        // assumed frequency of cpu
+    pub(crate) tick: i32,      // clock units into the current 60th of a second, times 60
+    ticked: bool,              // the 60Hz clock ticked since `begin_frame`
+    pub(crate) in_frame: bool, // a frame was begun and hasn't been ended
+
+    pub i: u16,                       // index register
+    pub pc: u16,                      // program counter
+    pub delay_timer: u8,              // delay timer
+    pub sound_timer: u8,              // sound timer
+    pub v: [u8; 16],                  // v registers 0x0-0xE
+    pub rpl: [u8; 16],                // SCHIP RPL user flags, kept across resets
+    pub seed: u64,                    // CXNN random seed, kept across resets
+    pub rng: Rng,                     // restarted from `seed` on reset
+    pub halted: bool,                 // set by 00FD (exit interpreter)
+    pub vblank: bool,                 // set at the start of each frame, see `Quirks::display_wait`
+    pub quirks: Quirks,               // opcode behaviour profile, kept across resets
+    pub timing: Timing,               // instruction costs, kept across resets
+    pub on_error: ErrorPolicy,        // what to do when the ROM faults
+    pub error: Option<EmulatorError>, // the fault that halted the machine
+    pub trace: Option<Tracer>,        // logs every executed instruction
+    pub audio_pattern: [u8; 16],      // XO-CHIP 1-bit audio pattern buffer
+    pub pitch: u8,                    // XO-CHIP playback pitch, 64 = 4000Hz
+    pub keypad: KeyPad,               // keypad
+    pub display: Display,             // display buffer
+    pub stack: Stack,                 // 16 frame stack
+    pub memory: Memory,               /* Memory Map
+                                       0x000-0x1FF - Chip 8 interpreter
+                                       0x00-0x50 - Used for the built in 4x5 pixel font set (0-F)
+                                       0x50-0xF0 - Used for the built in 8x10 SCHIP font set (0-F)
+                                       0x200-0xFFFF - Program ROM and work RAM (XO-CHIP)
+                                      */
 }
 
 impl Chip8 {
     pub fn init(hz: i32) -> Self {
         Self {
-            hz,
+            hz: hz.clamp(Self::MIN_HZ, Self::MAX_HZ),
             tick: 0,
+            ticked: false,
+            in_frame: false,
             i: 0,
             pc: 0x200,
             delay_timer: 0,
             sound_timer: 0,
             v: [0x0; 16],
+            rpl: [0x0; 16],
+            seed: 0,
+            rng: Rng::new(0),
+            halted: false,
+            vblank: false,
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            on_error: ErrorPolicy::default(),
+            error: None,
+            trace: None,
+            audio_pattern: Self::DEFAULT_AUDIO_PATTERN,
+            pitch: Self::DEFAULT_PITCH,
             keypad: KeyPad::new(),
             display: Display::new(),
             stack: Stack::new(),
@@ -36,24 +78,92 @@ impl Chip8 {
         }
     }
 
+    // speeds `set_hz` allows, instructions a second
+    pub const MIN_HZ: i32 = 60;
+    pub const MAX_HZ: i32 = 1_000_000;
+
     pub fn set_hz(&mut self, hz: i32) {
-        self.hz = hz.max(60);
+        self.hz = hz.clamp(Self::MIN_HZ, Self::MAX_HZ);
+    }
+
+    // a new seed takes effect immediately
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
     }
 
     pub fn reset(&mut self) {
         self.tick = 0;
+        self.ticked = false;
+        self.in_frame = false;
         self.i = 0;
         self.pc = 0x200;
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.v = [0; 16];
-        self.keypad = KeyPad::new();
+        self.rng = Rng::new(self.seed);
+        self.halted = false;
+        self.vblank = false;
+        self.error = None;
+        self.audio_pattern = Self::DEFAULT_AUDIO_PATTERN;
+        self.pitch = Self::DEFAULT_PITCH;
+        self.keypad.reset();
         self.display = Display::new();
-        self.memory = Memory::new();
+        self.stack = Stack::new();
+        self.memory.clear();
     }
 
     pub fn fetch(&mut self, pc: u16) -> u16 {
-        ((self.memory.get(pc) as u16) << 8) | (self.memory.get(pc + 1) as u16)
+        ((self.memory.get(pc) as u16) << 8) | (self.memory.get(pc.wrapping_add(1)) as u16)
+    }
+
+    // read memory at an address computed by the current instruction
+    pub fn read(&mut self, addr: u32) -> Result<u8, EmulatorError> {
+        match self.check_addr(addr)? {
+            Some(addr) => Ok(self.memory.get(addr)),
+            None => Ok(0),
+        }
+    }
+
+    // write memory at an address computed by the current instruction
+    pub fn write(&mut self, addr: u32, v: u8) -> Result<(), EmulatorError> {
+        if let Some(addr) = self.check_addr(addr)? {
+            self.memory.set(addr, v);
+        }
+        Ok(())
+    }
+
+    // the address to access, or None if the access should be dropped
+    fn check_addr(&self, addr: u32) -> Result<Option<u16>, EmulatorError> {
+        if addr < Memory::SIZE as u32 {
+            return Ok(Some(addr as u16));
+        }
+        match self.on_error {
+            ErrorPolicy::Halt => Err(EmulatorError::OutOfBounds { pc: self.pc, addr }),
+            ErrorPolicy::Wrap => Ok(Some(addr as u16)),
+            ErrorPolicy::Ignore => Ok(None),
+        }
+    }
+
+    pub fn push(&mut self, v: u16) -> Result<(), EmulatorError> {
+        if self.on_error == ErrorPolicy::Wrap {
+            self.stack.push_wrapping(v);
+        } else if !self.stack.push(v) && self.on_error == ErrorPolicy::Halt {
+            return Err(EmulatorError::StackOverflow { pc: self.pc });
+        }
+        Ok(())
+    }
+
+    // None if the pop should be dropped
+    pub fn pop(&mut self) -> Result<Option<u16>, EmulatorError> {
+        match self.on_error {
+            ErrorPolicy::Wrap => Ok(Some(self.stack.pop_wrapping())),
+            ErrorPolicy::Ignore => Ok(self.stack.pop()),
+            ErrorPolicy::Halt => match self.stack.pop() {
+                Some(v) => Ok(Some(v)),
+                None => Err(EmulatorError::StackUnderflow { pc: self.pc }),
+            },
+        }
     }
 
     pub fn load(&mut self, pos: u16, bytes: Vec<u8>) {
@@ -62,44 +172,154 @@ impl Chip8 {
         }
     }
 
-    pub fn tick_timers(&mut self) {
-        self.tick += 1;
-        if self.tick % (self.hz / 60) == 0 {
+    // the largest program that fits between 0x200 and the end of memory
+    pub const MAX_ROM_SIZE: usize = Memory::SIZE - 0x200;
+
+    pub fn check_rom(rom: &[u8]) -> Result<(), RomError> {
+        if rom.is_empty() {
+            Err(RomError::Empty)
+        } else if rom.len() > Self::MAX_ROM_SIZE {
+            Err(RomError::TooLarge {
+                size: rom.len(),
+                max: Self::MAX_ROM_SIZE,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // reset and start a new program at 0x200, keeping the machine untouched
+    // when the ROM doesn't fit
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        Self::check_rom(rom)?;
+        self.reset();
+        self.load_font(0x00);
+        self.load(0x200, rom.to_vec());
+        Ok(())
+    }
+
+    // advance the clock by `cost` units, ticking the timers at 60Hz
+    fn run_clock(&mut self, cost: i32) {
+        let rate = self.timing.rate(self.hz);
+        self.tick += cost * 60;
+        while self.tick >= rate {
+            self.tick -= rate;
             self.delay_timer = self.delay_timer.saturating_sub(1);
             self.sound_timer = self.sound_timer.saturating_sub(1);
-
-            #[cfg(target_arch = "wasm32")]
-            self.send_state(); // for front-end
+            self.ticked = true;
         }
+    }
+
+    // clock units left until the next 60Hz tick
+    fn until_tick(&self) -> i32 {
+        let rate = self.timing.rate(self.hz);
+        ((rate - self.tick + 59) / 60).max(1)
+    }
+
+    // will `instruction` wait for the next vblank instead of running now,
+    // see `Quirks::display_wait`
+    fn waits_for_vblank(&self, instruction: &Instruction) -> bool {
+        let draw = matches!(
+            instruction,
+            Instruction::Draw { .. } | Instruction::DrawBig { .. }
+        );
+        draw && !self.vblank && (self.quirks.display_wait || self.timing == Timing::Cycles)
+    }
 
-        if self.tick >= self.hz {
-            self.tick = 0;
+    fn cost(&self, instruction: &Instruction) -> i32 {
+        if self.timing == Timing::Cycles && self.waits_for_vblank(instruction) {
+            // stalls for the rest of the frame
+            self.until_tick()
+        } else {
+            self.timing.cost(instruction)
         }
     }
 
-    pub fn cycle(&mut self) {
-        self.tick_timers();
+    // run one instruction, a fault halts the machine unless `on_error` says otherwise
+    pub fn cycle(&mut self) -> Result<(), EmulatorError> {
+        if self.halted {
+            return Ok(());
+        }
 
         if self.keypad.waiting {
+            // keys only change between frames, so the VIP idles until the next
+            self.run_clock(match self.timing {
+                Timing::Instructions => 1,
+                Timing::Cycles => self.until_tick(),
+            });
             if let Some((key, dest)) = self.keypad.get_key() {
                 self.v[dest] = key;
             }
         } else {
             let opcode = self.fetch(self.pc);
-            self.exec_opcode(opcode);
+            let instruction = decode(opcode).unwrap_or(Instruction::Idle);
+            // log the state the instruction starts in, a draw that waits for
+            // vblank is logged once when it finally draws
+            if !self.waits_for_vblank(&instruction)
+                && self
+                    .trace
+                    .as_ref()
+                    .is_some_and(|t| t.wants(self.pc, opcode))
+            {
+                let entry = TraceEntry::capture(self, opcode);
+                self.trace.as_mut().unwrap().record(&entry);
+            }
+            self.run_clock(self.cost(&instruction));
+            if let Err(e) = self.exec_opcode(opcode) {
+                self.halted = true;
+                self.error = Some(e);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // start of a frame: poll input and open the vblank window
+    pub fn begin_frame(&mut self) {
+        self.keypad.poll();
+        self.vblank = true;
+        self.ticked = false;
+        self.in_frame = true;
+    }
+
+    // is a frame begun but not yet ended, like one stopped at a breakpoint
+    pub fn in_frame(&self) -> bool {
+        self.in_frame
+    }
+
+    // close the current frame if it has finished, returns whether it did
+    pub fn end_frame(&mut self) -> bool {
+        if self.frame_finished() {
+            self.in_frame = false;
         }
+        !self.in_frame
+    }
 
-        // if self.sound_timer > 0 {
-        //     // play a beep sound
-        // }
+    // has the current frame's 60Hz tick happened? There's nothing left to
+    // run in a halted machine.
+    pub fn frame_finished(&self) -> bool {
+        self.halted || self.ticked
     }
 
-    pub fn sync_cycle(&mut self, fps: i32) {
-        for _ in 0..((self.hz as f32 / fps.max(1) as f32).round() as i32) {
-            self.cycle();
+    // run a 60th of a second: instructions until the 60Hz clock ticks, on
+    // average exactly hz / 60 of them with `Timing::Instructions`
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        self.begin_frame();
+        while !self.frame_finished() {
+            self.cycle()?;
         }
+        self.end_frame();
+        Ok(())
     }
 
+    // a square wave, so XO-CHIP sound without F002 still beeps
+    pub const DEFAULT_AUDIO_PATTERN: [u8; 16] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, //
+    ];
+    pub const DEFAULT_PITCH: u8 = 64;
+
     const FONT: [u8; 80] = [
         0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
         0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -118,9 +338,124 @@ impl Chip8 {
         0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
         0xF0, 0x80, 0xF0, 0x80, 0x80, // F
     ];
+
+    pub const BIG_FONT_OFFSET: u16 = Self::FONT.len() as u16;
+    const BIG_FONT: [u8; 160] = [
+        0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+        0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+        0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+        0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+        0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+        0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+        0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ];
+
+    // labelled areas of memory once `load_rom` has loaded `rom_len` bytes,
+    // for memory viewers
+    pub fn memory_regions(rom_len: usize) -> Vec<(&'static str, RangeInclusive<u16>)> {
+        let font_end = Self::BIG_FONT_OFFSET + Self::BIG_FONT.len() as u16;
+        let program_end = 0x200 + (rom_len.clamp(1, Self::MAX_ROM_SIZE) - 1) as u16;
+        let mut regions = vec![
+            ("font", 0x000..=Self::BIG_FONT_OFFSET - 1),
+            ("big font", Self::BIG_FONT_OFFSET..=font_end - 1),
+            ("interpreter", font_end..=0x1FF),
+            ("program", 0x200..=program_end),
+        ];
+        if program_end < 0xFFFF {
+            regions.push(("ram", program_end + 1..=0xFFFF));
+        }
+        regions
+    }
+
+    // loads the small font at pos followed by the SCHIP big font
     pub fn load_font(&mut self, pos: u16) {
-        for (i, &f) in Self::FONT.iter().enumerate() {
+        for (i, &f) in Self::FONT.iter().chain(Self::BIG_FONT.iter()).enumerate() {
             self.memory.set(pos + (i as u16), f)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(rom: &[u8], timing: Timing) -> Chip8 {
+        let mut chippy = Chip8::init(500);
+        chippy.load_rom(rom).unwrap();
+        chippy.timing = timing;
+        chippy
+    }
+
+    #[test]
+    fn timers_tick_60_times_a_second() {
+        // 1200 loops forever
+        for hz in [61, 500, 700, 1000, 4321] {
+            let mut chippy = machine(&[0x12, 0x00], Timing::Instructions);
+            chippy.set_hz(hz);
+            chippy.delay_timer = 255;
+            chippy.sound_timer = 255;
+            for _ in 0..hz - 1 {
+                chippy.cycle().unwrap();
+            }
+            assert_eq!(chippy.delay_timer, 255 - 59, "{} hz", hz);
+            chippy.cycle().unwrap();
+            assert_eq!(chippy.delay_timer, 255 - 60, "{} hz", hz);
+            assert_eq!(chippy.sound_timer, 255 - 60, "{} hz", hz);
+            assert_eq!(chippy.tick, 0, "{} hz", hz);
+        }
+    }
+
+    #[test]
+    fn cycles_charge_vip_costs() {
+        let program = [
+            (0x6005, 27),        // V0 = 5
+            (0xA300, 55),        // I = 0x300
+            (0x8010, 200),       // V0 = V1
+            (0xF033, 927),       // BCD
+            (0xF255, 605 + 128), // store V0-V2
+            (0x3000, 55),        // skip if V0 == 0
+        ];
+        let rom: Vec<u8> = program
+            .iter()
+            .flat_map(|(op, _)| u16::to_be_bytes(*op))
+            .collect();
+        let mut chippy = machine(&rom, Timing::Cycles);
+        chippy.begin_frame();
+        for (opcode, micros) in program {
+            let tick = chippy.tick;
+            chippy.cycle().unwrap();
+            assert_eq!(chippy.tick - tick, micros * 60, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn cycles_draw_waits_for_vblank() {
+        let mut chippy = machine(&[0xD0, 0x05, 0xD0, 0x05], Timing::Cycles);
+        chippy.delay_timer = 10;
+        chippy.begin_frame();
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.pc, 0x202);
+        assert_eq!(chippy.tick, (68 + 102 * 5) * 60);
+
+        // the second draw burns the rest of the frame and is retried
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.pc, 0x202);
+        assert!(chippy.frame_finished());
+        assert_eq!(chippy.delay_timer, 9);
+        assert!(chippy.tick < 60);
+
+        chippy.end_frame();
+        chippy.begin_frame();
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.pc, 0x204);
+    }
+}
@@ -0,0 +1,537 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use chirp::{
+    assembler::assemble_file,
+    audio::Beeper,
+    chip8::Chip8,
+    debugger::parse_number,
+    disasm::{disassemble, Syntax},
+    input::Scripted,
+    movie::{rom_hash, Movie, MovieError},
+    quirks::Quirks,
+    roms::find_rom,
+    scheduler::Scheduler,
+    snapshot::{Diff, Snapshot, SnapshotFormat},
+    timing::Timing,
+    trace::{parse_classes, TraceFormat, Tracer},
+};
+use macroquad::color::Color;
+
+use crate::theme::{parse_colour, PixelStyle, Theme};
+
+const USAGE: &str = "usage: chirp [ROM] [options]
+  ROM                 a .ch8 file or a bundled ROM name (default brix)
+  --hz N              instructions per second, 60-1000000 (default 500)
+  --quirks PROFILE    chirp, vip, schip or xochip (default chirp)
+  --speed X           emulation speed, 2 is twice as fast (default 1)
+  --timing MODE       instructions (hz a second) or cycles (COSMAC VIP timing)
+  --seed N            random seed for CXNN (default from the clock, 0 for trace)
+  --palette NAME      chirp, green, amber, gameboy or contrast
+  --fg RRGGBB         pixel colour
+  --bg RRGGBB         background colour
+  --pixels STYLE      square, gaps, grid or rounded
+                      (display choices are remembered for next time)
+  --scale N           window pixels per CHIP-8 pixel, 1-100 (default 25)
+  --bloom             enable the bloom shader
+  --pitch HZ          frequency of the beep (default 440)
+  --fullscreen        start fullscreen
+  --paused            start paused in the debugger
+  --record FILE       record the keypad to a movie, saved on quit
+  --play FILE         play back a movie recorded with this ROM
+  --trace FILE        log executed instructions to FILE, - for stdout
+  --trace-format F    text or binary (default text)
+  --trace-range A-B   only log instructions between these addresses
+  --trace-ops CLASSES only log opcodes starting with these digits, like d,f or 0-3
+
+       chirp trace <rom> [--frames N] [options]
+       chirp snapshot <rom> [--frames N] [--keys SCRIPT] [-o OUT] [--format F]
+                      [--compare REF [--update]] [options]
+       chirp disasm <rom> [--syntax octo|classic] [--origin ADDR]
+       chirp asm <source> [-o OUT] [--symbols FILE]";
+
+// Command line subcommands that run without opening a window. Returns None
+// when `args` doesn't name one.
+//   chirp trace <rom> [--frames N] [--play MOVIE] [options]
+//   chirp snapshot <rom> [--frames N] [--keys SCRIPT] [-o OUT] [--compare REF] [options]
+//   chirp disasm <rom> [--syntax octo|classic] [--origin ADDR]
+//   chirp asm <source> [-o OUT] [--symbols FILE]
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    match args.first()?.as_str() {
+        "trace" => Some(trace(&args[1..])),
+        "snapshot" => Some(snapshot(&args[1..])),
+        "disasm" => Some(disasm(&args[1..])),
+        "asm" => Some(asm(&args[1..])),
+        "-h" | "--help" => {
+            println!("{}", USAGE);
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+// how to run the emulator window
+pub struct Options {
+    pub rom: Vec<u8>,
+    pub hz: i32,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub speed: f64,
+    pub seed: Option<u64>,
+    pub palette: Option<Theme>,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub pixels: Option<PixelStyle>,
+    pub scale: u32,
+    pub bloom: bool,
+    pub pitch: f32,
+    pub fullscreen: bool,
+    pub paused: bool,
+    pub trace: Option<Tracer>,
+    pub record: Option<String>,
+    pub play: Option<Movie>,
+}
+
+impl Options {
+    // keeps the window size in range
+    pub const MAX_SCALE: u32 = 100;
+
+    // `chirp [ROM] [options]`, see `USAGE`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut rom = None;
+        let mut options = Self {
+            rom: Vec::new(),
+            hz: 500,
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            speed: 1.0,
+            seed: None,
+            palette: None,
+            fg: None,
+            bg: None,
+            pixels: None,
+            scale: 25,
+            bloom: false,
+            pitch: Beeper::new().pitch,
+            fullscreen: false,
+            paused: false,
+            trace: None,
+            record: None,
+            play: None,
+        };
+        let mut trace_path = None;
+        let mut trace_format = TraceFormat::Text;
+        let mut trace_range = 0..=0xFFFF;
+        let mut trace_ops = 0xFFFF;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(format!("{} needs a value\n{}", arg, USAGE))
+            };
+            match arg.as_str() {
+                "--hz" => {
+                    let hz = value()?;
+                    options.hz = hz
+                        .parse()
+                        .ok()
+                        .filter(|hz| (Chip8::MIN_HZ..=Chip8::MAX_HZ).contains(hz))
+                        .ok_or(format!("bad hz {}", hz))?;
+                }
+                "--quirks" => {
+                    let name = value()?;
+                    options.quirks = Quirks::from_name(name).ok_or(format!(
+                        "unknown quirks profile {}, expected one of {}",
+                        name,
+                        Quirks::PROFILES.join(", ")
+                    ))?;
+                }
+                "--timing" => {
+                    let name = value()?;
+                    options.timing = Timing::from_name(name).ok_or(format!(
+                        "unknown timing {}, expected one of {}",
+                        name,
+                        Timing::NAMES.join(", ")
+                    ))?;
+                }
+                "--speed" => {
+                    let speed = value()?;
+                    options.speed = speed
+                        .parse()
+                        .ok()
+                        .filter(|s| (Scheduler::MIN_SPEED..=Scheduler::MAX_SPEED).contains(s))
+                        .ok_or(format!(
+                            "bad speed {}, expected {} to {}",
+                            speed,
+                            Scheduler::MIN_SPEED,
+                            Scheduler::MAX_SPEED
+                        ))?;
+                }
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|_| format!("bad seed {}", seed))?);
+                }
+                "--palette" => {
+                    let name = value()?;
+                    options.palette = Some(Theme::palette(name).ok_or(format!(
+                        "unknown palette {}, expected one of {}",
+                        name,
+                        Theme::PALETTES.map(|(name, _, _)| name).join(", ")
+                    ))?);
+                }
+                "--fg" => options.fg = Some(parse_colour(value()?)?),
+                "--bg" => options.bg = Some(parse_colour(value()?)?),
+                "--pixels" => {
+                    let name = value()?;
+                    options.pixels = Some(PixelStyle::from_name(name).ok_or(format!(
+                        "unknown pixel style {}, expected one of {}",
+                        name,
+                        PixelStyle::NAMES.join(", ")
+                    ))?);
+                }
+                "--scale" => {
+                    let scale = value()?;
+                    options.scale = scale
+                        .parse()
+                        .ok()
+                        .filter(|scale| (1..=Self::MAX_SCALE).contains(scale))
+                        .ok_or(format!(
+                            "bad scale {}, expected 1 to {}",
+                            scale,
+                            Self::MAX_SCALE
+                        ))?;
+                }
+                "--bloom" => options.bloom = true,
+                "--pitch" => {
+                    let pitch = value()?;
+                    options.pitch = pitch
+                        .parse()
+                        .ok()
+                        .filter(|&pitch: &f32| pitch.is_finite() && pitch > 0.0)
+                        .ok_or(format!("bad pitch {}", pitch))?;
+                }
+                "--fullscreen" => options.fullscreen = true,
+                "--paused" => options.paused = true,
+                "--record" => options.record = Some(value()?.clone()),
+                "--play" => {
+                    let path = value()?;
+                    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+                    let movie = Movie::from_bytes(&data).map_err(|e| format!("{}: {}", path, e))?;
+                    options.play = Some(movie);
+                }
+                "--trace" => trace_path = Some(value()?),
+                "--trace-format" => {
+                    let name = value()?;
+                    trace_format = TraceFormat::from_name(name)
+                        .ok_or(format!("unknown trace format {}", name))?;
+                }
+                "--trace-range" => {
+                    let range = value()?;
+                    trace_range =
+                        parse_range(range).ok_or(format!("bad address range {}", range))?;
+                }
+                "--trace-ops" => {
+                    let ops = value()?;
+                    trace_ops = parse_classes(ops).ok_or(format!("bad opcode classes {}", ops))?;
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("unknown option {}\n{}", arg, USAGE))
+                }
+                _ if rom.is_none() => rom = Some(read_rom(arg)?),
+                _ => return Err(format!("unexpected argument {}\n{}", arg, USAGE)),
+            }
+        }
+
+        options.rom = match rom {
+            Some(rom) => rom,
+            None => find_rom("brix").unwrap().to_vec(),
+        };
+        Chip8::check_rom(&options.rom).map_err(|e| e.to_string())?;
+        if let Some(movie) = &options.play {
+            if movie.rom_hash != rom_hash(&options.rom) {
+                return Err(MovieError::WrongRom.to_string());
+            }
+        }
+
+        if let Some(path) = trace_path {
+            let out: Box<dyn Write + Send> = if path == "-" {
+                Box::new(io::stdout())
+            } else {
+                let file = fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+                Box::new(BufWriter::new(file))
+            };
+            let mut tracer = Tracer::new(out, trace_format);
+            tracer.range = trace_range;
+            tracer.classes = trace_ops;
+            options.trace = Some(tracer);
+        }
+        Ok(options)
+    }
+}
+
+// START-END or a single address
+fn parse_range(s: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end) = (parse_number(start.trim())?, parse_number(end.trim())?);
+    Some(start.min(end)..=start.max(end))
+}
+
+// run `options.rom` without a window for `frames` 60Hz frames. With --play
+// the movie's keys are fed in and it runs to the movie's end by default,
+// otherwise keys come from `input`. The seed defaults to 0 so runs repeat.
+fn run_headless(options: Options, frames: Option<usize>, input: Option<Scripted>) -> Chip8 {
+    let mut chippy = Chip8::init(options.hz);
+    chippy.quirks = options.quirks;
+    chippy.timing = options.timing;
+    chippy.seed = options.seed.unwrap_or(0);
+    chippy.load_rom(&options.rom).unwrap();
+    chippy.trace = options.trace;
+    let frames = match &options.play {
+        Some(movie) => {
+            movie.play(&mut chippy, &options.rom).unwrap();
+            frames.unwrap_or(movie.frames.len())
+        }
+        None => {
+            if let Some(input) = input {
+                chippy.keypad.set_input(input);
+            }
+            frames.unwrap_or(600)
+        }
+    };
+    for _ in 0..frames {
+        if let Err(e) = chippy.run_frame() {
+            eprintln!("chirp: {}", e);
+            break;
+        }
+    }
+    chippy
+}
+
+fn parse_frames(n: Option<&String>) -> Result<usize, String> {
+    let n = n.ok_or("--frames needs a value")?;
+    n.parse().map_err(|_| format!("bad frame count {}", n))
+}
+
+// run a ROM without a window and log its trace, to stdout unless --trace
+// says otherwise. With --play a recorded session can be traced.
+fn trace(args: &[String]) -> Result<(), String> {
+    let mut frames = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = Some(parse_frames(args.next())?),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    if !rest.iter().any(|arg| arg == "--trace") {
+        rest.extend(["--trace".to_owned(), "-".to_owned()]);
+    }
+    let options = Options::parse(&rest)?;
+
+    let mut chippy = run_headless(options, frames, None);
+    let mut tracer = chippy.trace.take().unwrap();
+    tracer.flush().map_err(|e| e.to_string())
+}
+
+// run a ROM without a window and write what's on screen at the end, as
+// ASCII to stdout unless -o says otherwise. With --compare the screen is
+// checked against a reference snapshot instead, failing with a pixel diff
+// if they differ, and --update (re)writes the reference.
+fn snapshot(args: &[String]) -> Result<(), String> {
+    let mut frames = None;
+    let mut keys = None;
+    let mut out = None;
+    let mut format = None;
+    let mut reference = None;
+    let mut update = false;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--frames" => frames = Some(parse_frames(args.next())?),
+            "--keys" => {
+                let script = value()?;
+                keys = Some(Scripted::parse(script).ok_or(format!(
+                    "bad key script {}, expected FRAMES:KEYS steps like 60:-,10:5",
+                    script
+                ))?);
+            }
+            "-o" => out = Some(value()?.clone()),
+            "--format" => {
+                let name = value()?;
+                format = Some(SnapshotFormat::from_name(name).ok_or(format!(
+                    "unknown snapshot format {}, expected one of {}",
+                    name,
+                    SnapshotFormat::NAMES.join(", ")
+                ))?);
+            }
+            "--compare" => reference = Some(value()?.clone()),
+            "--update" => update = true,
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let options = Options::parse(&rest)?;
+    if keys.is_some() && options.play.is_some() {
+        return Err("--keys and --play can't be used together".to_owned());
+    }
+    let mut palette = Snapshot::PALETTE;
+    if let Some(theme) = options.palette {
+        palette[1] = rgb(theme.foreground);
+        palette[0] = rgb(theme.background);
+    }
+    if let Some(fg) = options.fg {
+        palette[1] = rgb(fg);
+    }
+    if let Some(bg) = options.bg {
+        palette[0] = rgb(bg);
+    }
+
+    let chippy = run_headless(options, frames, keys);
+    let actual = Snapshot::from_display(&chippy.display);
+
+    let write = |path: &str, format: Option<SnapshotFormat>| {
+        let format = format.unwrap_or(SnapshotFormat::from_path(path));
+        fs::write(path, actual.encode(format, &palette)).map_err(|e| format!("{}: {}", path, e))
+    };
+    if let Some(path) = &out {
+        write(path, format)?;
+    }
+    match reference {
+        Some(path) if update => write(&path, None),
+        Some(path) => {
+            let format = SnapshotFormat::from_path(&path);
+            let data = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let expected =
+                Snapshot::decode(&data, format).map_err(|e| format!("{}: {}", path, e))?;
+            // PBM only knows lit and unlit
+            let actual = match format {
+                SnapshotFormat::Pbm => actual.mono(),
+                _ => actual,
+            };
+            match actual.diff(&expected) {
+                Diff::Same => Ok(()),
+                diff => Err(format!("{} doesn't match: {}", path, diff)),
+            }
+        }
+        None if out.is_none() => {
+            let format = format.unwrap_or(SnapshotFormat::Ascii);
+            io::stdout()
+                .write_all(&actual.encode(format, &palette))
+                .map_err(|e| e.to_string())
+        }
+        None => Ok(()),
+    }
+}
+
+fn rgb(colour: Color) -> [u8; 3] {
+    let [r, g, b, _]: [u8; 4] = colour.into();
+    [r, g, b]
+}
+
+// a file path, or the name of a bundled ROM
+pub fn read_rom(arg: &str) -> Result<Vec<u8>, String> {
+    match fs::read(arg) {
+        Ok(rom) => Ok(rom),
+        Err(e) => match find_rom(arg) {
+            Some(rom) => Ok(rom.to_vec()),
+            None => Err(format!("{}: {}", arg, e)),
+        },
+    }
+}
+
+fn disasm(args: &[String]) -> Result<(), String> {
+    let mut rom = None;
+    let mut syntax = Syntax::Octo;
+    let mut origin = 0x200;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = args.next().ok_or("--syntax needs a value")?;
+                syntax = Syntax::from_name(name).ok_or(format!("unknown syntax {}", name))?;
+            }
+            "--origin" => {
+                let addr = args.next().ok_or("--origin needs an address")?;
+                origin = parse_number(addr).ok_or(format!("bad origin {}", addr))?;
+            }
+            _ if rom.is_none() => rom = Some(read_rom(arg)?),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let rom = rom.ok_or("usage: chirp disasm <rom> [--syntax octo|classic] [--origin ADDR]")?;
+    print!("{}", disassemble(&rom, origin, syntax));
+    Ok(())
+}
+
+fn asm(args: &[String]) -> Result<(), String> {
+    let mut source = None;
+    let mut out = None;
+    let mut symbols = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => out = Some(args.next().ok_or("-o needs a path")?),
+            "--symbols" => symbols = Some(args.next().ok_or("--symbols needs a path")?),
+            _ if source.is_none() => source = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let source = source.ok_or("usage: chirp asm <source> [-o OUT] [--symbols FILE]")?;
+    let program = assemble_file(Path::new(source)).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    let out = match out {
+        Some(out) => out.to_owned(),
+        None => Path::new(source)
+            .with_extension("ch8")
+            .display()
+            .to_string(),
+    };
+    fs::write(&out, &program.bytes).map_err(|e| format!("{}: {}", out, e))?;
+    if let Some(path) = symbols {
+        fs::write(path, program.symbol_map()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(parse(&["--scale", "100"]).unwrap().scale, 100);
+        for bad in ["0", "101", "100000000", "-1", "x"] {
+            assert!(parse(&["--scale", bad]).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn hz() {
+        assert_eq!(parse(&["--hz", "700"]).unwrap().hz, 700);
+        let Err(e) = parse(&["--hz", "59"]) else {
+            panic!("59 hz accepted");
+        };
+        assert_eq!(e, "bad hz 59");
+    }
+}
@@ -0,0 +1,470 @@
+// Interactive debugger.
+//
+// Frontends call `Debugger::run_frame` instead of `Chip8::run_frame`. It runs
+// the frame one instruction at a time and stops before a breakpoint, after a
+// write to a watched address (see `Memory::watch`) or when a step finishes.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{
+    chip8::Chip8,
+    error::EmulatorError,
+    instruction::{decode, Instruction},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Delay,
+    Sound,
+    StackDepth,
+}
+
+impl Register {
+    pub fn get(&self, chippy: &Chip8) -> u16 {
+        match *self {
+            Self::V(x) => chippy.v[(x & 0xF) as usize] as u16,
+            Self::I => chippy.i,
+            Self::Delay => chippy.delay_timer as u16,
+            Self::Sound => chippy.sound_timer as u16,
+            Self::StackDepth => chippy.stack.depth() as u16,
+        }
+    }
+
+    // v0-vf, i, dt, st, sp
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "i" => Some(Self::I),
+            "dt" => Some(Self::Delay),
+            "st" => Some(Self::Sound),
+            "sp" => Some(Self::StackDepth),
+            _ => {
+                let x = name.strip_prefix('v')?;
+                if x.len() != 1 {
+                    return None;
+                }
+                u8::from_str_radix(x, 16).ok().map(Self::V)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V(x) => write!(f, "v{:x}", x),
+            Self::I => write!(f, "i"),
+            Self::Delay => write!(f, "dt"),
+            Self::Sound => write!(f, "st"),
+            Self::StackDepth => write!(f, "sp"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    const OPERATORS: [(&'static str, Cmp); 6] = [
+        ("==", Cmp::Eq),
+        ("!=", Cmp::Ne),
+        ("<=", Cmp::Le),
+        (">=", Cmp::Ge),
+        ("<", Cmp::Lt),
+        (">", Cmp::Gt),
+    ];
+
+    pub fn test(&self, a: u16, b: u16) -> bool {
+        match self {
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, _) = Self::OPERATORS.iter().find(|(_, c)| c == self).unwrap();
+        write!(f, "{}", op)
+    }
+}
+
+// a register test like `v3 == 0x10` or `i >= 0x300`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub reg: Register,
+    pub cmp: Cmp,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, chippy: &Chip8) -> bool {
+        self.cmp.test(self.reg.get(chippy), self.value)
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let (pos, op, cmp) = Cmp::OPERATORS
+            .iter()
+            .filter_map(|&(op, cmp)| s.find(op).map(|pos| (pos, op, cmp)))
+            .min_by_key(|&(pos, op, _)| (pos, usize::MAX - op.len()))?;
+        Some(Self {
+            reg: Register::from_name(s[..pos].trim())?,
+            cmp,
+            value: parse_number(s[pos + op.len()..].trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:#x}", self.reg, self.cmp, self.value)
+    }
+}
+
+// decimal, or hex with a 0x or $ prefix
+pub fn parse_number(s: &str) -> Option<u16> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>, // only break when this holds
+}
+
+impl Breakpoint {
+    pub fn new(addr: u16) -> Self {
+        Self {
+            addr,
+            condition: None,
+        }
+    }
+
+    pub fn when(addr: u16, condition: Condition) -> Self {
+        Self {
+            addr,
+            condition: Some(condition),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Pause,
+    Step,
+    Breakpoint(u16),
+    Watchpoint { addr: u16, value: u8 },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pause => write!(f, "paused"),
+            Self::Step => write!(f, "step"),
+            Self::Breakpoint(addr) => write!(f, "breakpoint at {:#05x}", addr),
+            Self::Watchpoint { addr, value } => {
+                write!(f, "watchpoint: wrote {:#04x} to {:#05x}", value, addr)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Over { ret: u16, depth: usize }, // until the call at ret - 2 returns
+    Out { depth: usize },            // until the stack drops below depth
+}
+
+pub struct Debugger {
+    pub paused: bool,
+    pub stop: Option<StopReason>, // why we last stopped
+    breakpoints: Vec<Breakpoint>,
+    step: Option<Step>,
+    resume_at: Option<u16>, // don't break again on the breakpoint we resumed from
+    pub symbols: BTreeMap<String, u16>, // labels from `assembler::Program::symbols`
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            paused: false,
+            stop: None,
+            breakpoints: Vec::new(),
+            step: None,
+            resume_at: None,
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    // an address given as a number or a symbol name
+    pub fn resolve(&self, s: &str) -> Option<u16> {
+        parse_number(s).or_else(|| self.symbols.get(s).copied())
+    }
+
+    // the name of a label at exactly `addr`
+    pub fn symbol_at(&self, addr: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|&(_, &a)| a == addr)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    // replaces any breakpoint at the same address
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) {
+        self.remove_breakpoint(bp.addr);
+        self.breakpoints.push(bp);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|bp| bp.addr != addr);
+        self.breakpoints.len() != len
+    }
+
+    pub fn toggle_breakpoint(&mut self, addr: u16) {
+        if !self.remove_breakpoint(addr) {
+            self.add_breakpoint(Breakpoint::new(addr));
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.halt(StopReason::Pause);
+    }
+
+    pub fn resume(&mut self, chippy: &Chip8) {
+        self.paused = false;
+        self.stop = None;
+        self.step = None;
+        self.resume_at = Some(chippy.pc);
+    }
+
+    pub fn toggle_pause(&mut self, chippy: &Chip8) {
+        if self.paused {
+            self.resume(chippy);
+        } else {
+            self.pause();
+        }
+    }
+
+    // run a single instruction and pause
+    pub fn step_into(&mut self, chippy: &mut Chip8) -> Result<(), EmulatorError> {
+        // a step at the start of a frame sees that frame's keys, and a step
+        // after the 60Hz tick starts the next frame
+        if chippy.end_frame() {
+            chippy.begin_frame();
+        }
+        chippy.memory.take_watch_hit();
+        let result = chippy.cycle();
+        self.halt(match chippy.memory.take_watch_hit() {
+            Some((addr, value)) => StopReason::Watchpoint { addr, value },
+            None => StopReason::Step,
+        });
+        result
+    }
+
+    // like `step_into`, but runs a 2NNN subroutine call to completion
+    pub fn step_over(&mut self, chippy: &mut Chip8) -> Result<(), EmulatorError> {
+        let call = matches!(
+            decode(chippy.fetch(chippy.pc)),
+            Ok(Instruction::Call { .. })
+        );
+        if chippy.keypad.waiting || !call {
+            return self.step_into(chippy);
+        }
+        self.resume(chippy);
+        self.step = Some(Step::Over {
+            ret: chippy.pc.wrapping_add(2),
+            depth: chippy.stack.depth(),
+        });
+        Ok(())
+    }
+
+    // run until the current subroutine returns
+    pub fn step_out(&mut self, chippy: &mut Chip8) -> Result<(), EmulatorError> {
+        let depth = chippy.stack.depth();
+        if depth == 0 {
+            return self.step_into(chippy);
+        }
+        self.resume(chippy);
+        self.step = Some(Step::Out { depth });
+        Ok(())
+    }
+
+    // run a frame unless paused, stopping early when the debugger triggers.
+    // A stopped frame carries on where it left off next time; returns true
+    // once the frame has finished.
+    pub fn run_frame(&mut self, chippy: &mut Chip8) -> Result<bool, EmulatorError> {
+        if self.paused {
+            return Ok(false);
+        }
+        if !chippy.in_frame() {
+            chippy.begin_frame();
+        }
+        while !chippy.frame_finished() {
+            if let Some(addr) = self.breakpoint(chippy) {
+                self.halt(StopReason::Breakpoint(addr));
+                break;
+            }
+
+            chippy.memory.take_watch_hit();
+            chippy.cycle()?;
+
+            if let Some((addr, value)) = chippy.memory.take_watch_hit() {
+                self.halt(StopReason::Watchpoint { addr, value });
+                break;
+            }
+            if self.step_done(chippy) {
+                self.halt(StopReason::Step);
+                break;
+            }
+        }
+        Ok(chippy.end_frame())
+    }
+
+    fn halt(&mut self, reason: StopReason) {
+        self.paused = true;
+        self.stop = Some(reason);
+        self.step = None;
+        self.resume_at = None;
+    }
+
+    // the breakpoint about to be hit, if any
+    fn breakpoint(&mut self, chippy: &Chip8) -> Option<u16> {
+        // nothing executes while halted or waiting for a key
+        if chippy.halted || chippy.keypad.waiting {
+            return None;
+        }
+        let pc = chippy.pc;
+        if self.resume_at.take() == Some(pc) {
+            return None;
+        }
+        self.breakpoints
+            .iter()
+            .find(|bp| bp.addr == pc && bp.condition.is_none_or(|c| c.holds(chippy)))
+            .map(|bp| bp.addr)
+    }
+
+    fn step_done(&self, chippy: &Chip8) -> bool {
+        match self.step {
+            Some(Step::Over { ret, depth }) => chippy.pc == ret && chippy.stack.depth() == depth,
+            Some(Step::Out { depth }) => chippy.stack.depth() < depth,
+            None => false,
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::Scripted, quirks::Quirks, roms, timing::Timing};
+
+    fn machine() -> Chip8 {
+        let mut chippy = Chip8::init(1000);
+        chippy.reseed(3);
+        chippy.load_rom(roms::Brix).unwrap();
+        // move the paddle once the game has started, brix reads keys 4 and 6
+        chippy
+            .keypad
+            .set_input(Scripted::new().wait(70).hold(&[4], 20).hold(&[6], 60));
+        chippy
+    }
+
+    #[test]
+    fn breakpoints_keep_frames_whole() {
+        let mut plain = machine();
+        for _ in 0..120 {
+            plain.run_frame().unwrap();
+        }
+
+        // brix's start up delay and main loop both run several times a frame
+        let mut chippy = machine();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::new(0x234));
+        debugger.add_breakpoint(Breakpoint::new(0x246));
+        let (mut frames, mut stops) = (0, 0);
+        while frames < 120 {
+            if debugger.paused {
+                debugger.resume(&chippy);
+                stops += 1;
+            }
+            if debugger.run_frame(&mut chippy).unwrap() {
+                frames += 1;
+            }
+        }
+        assert!(stops > 120, "{} stops", stops);
+        assert_eq!(chippy.save_state(), plain.save_state());
+    }
+
+    #[test]
+    fn steps_belong_to_the_frame() {
+        let mut plain = machine();
+        for _ in 0..120 {
+            plain.run_frame().unwrap();
+        }
+
+        let mut chippy = machine();
+        let mut debugger = Debugger::new();
+        let mut frames = 0;
+        while frames < 120 {
+            // a few steps at the start of every other frame
+            if frames % 2 == 0 && !chippy.in_frame() {
+                for _ in 0..3 {
+                    debugger.step_into(&mut chippy).unwrap();
+                }
+                debugger.resume(&chippy);
+            }
+            if debugger.run_frame(&mut chippy).unwrap() {
+                frames += 1;
+            }
+        }
+        assert_eq!(chippy.save_state(), plain.save_state());
+    }
+
+    #[test]
+    fn steps_cross_frames() {
+        // draw the 0 glyph three times, each DXYN waits for its own vblank
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05];
+        for timing in [Timing::Instructions, Timing::Cycles] {
+            let mut chippy = Chip8::init(1000);
+            chippy.load_rom(&rom).unwrap();
+            chippy.quirks = Quirks::VIP;
+            chippy.timing = timing;
+            let mut debugger = Debugger::new();
+            for _ in 0..1000 {
+                if chippy.pc == 0x20A {
+                    break;
+                }
+                debugger.step_into(&mut chippy).unwrap();
+            }
+            assert_eq!(chippy.pc, 0x20A, "{:?}", timing);
+            assert!(debugger.paused);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use chirp::{chip8::Chip8, debugger::Debugger};
+use macroquad::prelude::*;
+
+// Debugger keys for the window, away from the keypad keys.
+//   P pause/resume, N step, O step over a call, U step out, B toggle a
+//   breakpoint at the pc
+pub fn update(debugger: &mut Debugger, chippy: &mut Chip8) {
+    let result = if is_key_pressed(KeyCode::P) {
+        debugger.toggle_pause(chippy);
+        Ok(())
+    } else if is_key_pressed(KeyCode::N) {
+        debugger.step_into(chippy)
+    } else if is_key_pressed(KeyCode::O) {
+        debugger.step_over(chippy)
+    } else if is_key_pressed(KeyCode::U) {
+        debugger.step_out(chippy)
+    } else {
+        if is_key_pressed(KeyCode::B) {
+            debugger.toggle_breakpoint(chippy.pc);
+        }
+        Ok(())
+    };
+    if let Err(e) = result {
+        eprintln!("chirp: {}", e);
+    }
+}
+
+// register overlay while paused
+pub fn draw(debugger: &Debugger, chippy: &Chip8) {
+    if !debugger.paused {
+        return;
+    }
+
+    let mut lines = vec![
+        match debugger.stop {
+            Some(reason) => format!("{}", reason),
+            None => "paused".to_owned(),
+        },
+        format!(
+            "pc {:04X}{} op {:04X}  i {:04X}  dt {:02X} st {:02X}  sp {}",
+            chippy.pc,
+            debugger
+                .symbol_at(chippy.pc)
+                .map(|name| format!(" ({})", name))
+                .unwrap_or_default(),
+            opcode_at(chippy, chippy.pc),
+            chippy.i,
+            chippy.delay_timer,
+            chippy.sound_timer,
+            chippy.stack.depth(),
+        ),
+    ];
+    for half in chippy.v.chunks(8) {
+        lines.push(
+            half.iter()
+                .map(|v| format!("{:02X}", v))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    for bp in debugger.breakpoints() {
+        lines.push(match bp.condition {
+            Some(c) => format!("break {:04X} if {}", bp.addr, c),
+            None => format!("break {:04X}", bp.addr),
+        });
+    }
+
+    for (n, line) in lines.iter().enumerate() {
+        draw_text(line, 8.0, 64.0 + 28.0 * n as f32, 28.0, YELLOW);
+    }
+}
+
+// like `Chip8::fetch`, without touching the heatmap
+pub fn opcode_at(chippy: &Chip8, pc: u16) -> u16 {
+    let mem = chippy.memory.bytes();
+    u16::from_be_bytes([mem[pc as usize], mem[pc.wrapping_add(1) as usize]])
+}
@@ -0,0 +1,285 @@
+// Disassembler for CHIP-8, SCHIP and XO-CHIP programs.
+//
+// Opcodes are decoded with `instruction::decode`, the same decoder the
+// interpreter runs. Code is found by following control flow from the entry
+// point, so bytes that are never reached (sprites, tables) are listed as data
+// instead of garbage instructions.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::instruction::{decode, decode_long, Instruction};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    Octo,    // `v0 := 0x05`, `if v0 != 3 then`, ...
+    Classic, // `LD V0, 0x05`, `SE V0, 0x03`, ...
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "octo" => Some(Self::Octo),
+            "classic" => Some(Self::Classic),
+            _ => None,
+        }
+    }
+
+    fn comment(&self) -> char {
+        match self {
+            Self::Octo => '#',
+            Self::Classic => ';',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Data, // target of `i := NNN`
+    Loc,  // jump target
+    Sub,  // call target
+    Main, // entry point
+}
+
+// a single instruction with numeric operands, `long` is the word after the
+// opcode and only used by F000
+pub fn mnemonic(opcode: u16, long: u16, syntax: Syntax) -> Option<String> {
+    let instruction = decode_long(opcode, long).ok()?;
+    Some(text(&instruction, syntax, &|addr| format!("{:#05x}", addr)))
+}
+
+fn text(instruction: &Instruction, syntax: Syntax, name: &dyn Fn(u16) -> String) -> String {
+    match syntax {
+        Syntax::Octo => instruction.octo(name),
+        Syntax::Classic => instruction.classic(name),
+    }
+}
+
+// a listing of `rom` loaded at `origin`, one instruction or up to 8 data bytes
+// per line, each followed by its address and raw bytes
+pub fn disassemble(rom: &[u8], origin: u16, syntax: Syntax) -> String {
+    let end = origin as usize + rom.len();
+    let (code, labels) = trace(rom, origin);
+    let name = |addr: u16| match labels.get(&addr) {
+        Some(&label) => label_name(label, addr),
+        None => format!("{:#05x}", addr),
+    };
+    let word = |addr: usize| {
+        let i = addr - origin as usize;
+        u16::from_be_bytes([rom[i], *rom.get(i + 1).unwrap_or(&0)])
+    };
+    // only list an instruction when nothing else starts inside it
+    let fits = |addr: usize, size: usize| {
+        addr + size <= end
+            && (addr + 1..addr + size).all(|a| {
+                let a = a as u16;
+                !labels.contains_key(&a) && !code.contains(&a)
+            })
+    };
+
+    let mut out = String::new();
+    let mut addr = origin as usize;
+    while addr < end {
+        if let Some(&label) = labels.get(&(addr as u16)) {
+            match syntax {
+                Syntax::Octo => writeln!(out, ": {}", label_name(label, addr as u16)),
+                Syntax::Classic => writeln!(out, "{}:", label_name(label, addr as u16)),
+            }
+            .unwrap();
+        }
+
+        let opcode = word(addr);
+        let size = if opcode == 0xF000 { 4 } else { 2 };
+        if code.contains(&(addr as u16)) && fits(addr, size) {
+            let long = if size == 4 { word(addr + 2) } else { 0 };
+            let text = text(&decode_long(opcode, long).unwrap(), syntax, &name);
+            let raw = if size == 4 {
+                format!("{:04X} {:04X}", opcode, long)
+            } else {
+                format!("{:04X}", opcode)
+            };
+            line(&mut out, &text, syntax, addr, &raw);
+            addr += size;
+            continue;
+        }
+
+        // data runs until the next label or instruction
+        let start = addr;
+        addr += 1;
+        while addr < end
+            && addr - start < 8
+            && !labels.contains_key(&(addr as u16))
+            && !code.contains(&(addr as u16))
+        {
+            addr += 1;
+        }
+        let bytes = &rom[start - origin as usize..addr - origin as usize];
+        let text: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+        let text = match syntax {
+            Syntax::Octo => text.join(" "),
+            Syntax::Classic => format!("db {}", text.join(", ")),
+        };
+        let raw: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        line(&mut out, &text, syntax, start, &raw.join(""));
+    }
+    out
+}
+
+fn line(out: &mut String, text: &str, syntax: Syntax, addr: usize, raw: &str) {
+    writeln!(
+        out,
+        "    {:<31} {} {:04X}  {}",
+        text,
+        syntax.comment(),
+        addr,
+        raw
+    )
+    .unwrap();
+}
+
+fn label_name(label: Label, addr: u16) -> String {
+    match label {
+        Label::Main => "main".to_owned(),
+        Label::Sub => format!("sub_{:03x}", addr),
+        Label::Loc => format!("loc_{:03x}", addr),
+        Label::Data => format!("data_{:03x}", addr),
+    }
+}
+
+// follow every path from `origin`, returning where instructions start and the
+// addresses that deserve a label
+fn trace(rom: &[u8], origin: u16) -> (BTreeSet<u16>, BTreeMap<u16, Label>) {
+    let end = origin as usize + rom.len();
+    let in_rom = |addr: u16| (origin as usize..end).contains(&(addr as usize));
+    let word = |addr: u16| -> Option<u16> {
+        let i = (addr as usize).checked_sub(origin as usize)?;
+        Some(u16::from_be_bytes([*rom.get(i)?, *rom.get(i + 1)?]))
+    };
+
+    let mut code = BTreeSet::new();
+    let mut labels = BTreeMap::new();
+    let label = |labels: &mut BTreeMap<u16, Label>, addr: u16, kind: Label| {
+        if in_rom(addr) {
+            let l = labels.entry(addr).or_insert(kind);
+            *l = (*l).max(kind);
+        }
+    };
+    label(&mut labels, origin, Label::Main);
+
+    let mut todo = vec![origin];
+    while let Some(addr) = todo.pop() {
+        if code.contains(&addr) {
+            continue;
+        }
+        let Some(opcode) = word(addr) else {
+            continue;
+        };
+        // 0000 is legal but never shows up in real code, it's almost always data
+        let Some(instruction) = decode(opcode).ok().filter(|&i| i != Instruction::Idle) else {
+            continue;
+        };
+        code.insert(addr);
+
+        let next = addr.wrapping_add(instruction.size());
+        match instruction {
+            _ if instruction.is_skip() => {
+                let skipped = if word(next) == Some(0xF000) { 4 } else { 2 };
+                todo.push(next);
+                todo.push(next.wrapping_add(skipped));
+            }
+            Instruction::Jump { nnn } => {
+                label(&mut labels, nnn, Label::Loc);
+                todo.push(nnn);
+            }
+            Instruction::Call { nnn } => {
+                label(&mut labels, nnn, Label::Sub);
+                todo.push(nnn);
+                todo.push(next);
+            }
+            // jump tables usually start at nnn
+            Instruction::JumpOffset { nnn } => {
+                label(&mut labels, nnn, Label::Loc);
+                todo.push(nnn);
+            }
+            Instruction::Return | Instruction::Exit => {}
+            Instruction::SetI { nnn } => {
+                label(&mut labels, nnn, Label::Data);
+                todo.push(next);
+            }
+            Instruction::SetILong { .. } => {
+                if let Some(long) = word(addr.wrapping_add(2)) {
+                    label(&mut labels, long, Label::Data);
+                }
+                todo.push(next);
+            }
+            _ => todo.push(next),
+        }
+    }
+    (code, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roms;
+
+    #[test]
+    fn brix() {
+        let listing = disassemble(roms::Brix, 0x200, Syntax::Octo);
+        let head = "\
+: main
+    ve := 0x05                      # 0200  6E05
+    v5 := 0x00                      # 0202  6500
+    vb := 0x06                      # 0204  6B06
+: loc_206
+    va := 0x00                      # 0206  6A00
+: loc_208
+    i := data_30c                   # 0208  A30C
+    sprite va vb 1                  # 020A  DAB1
+";
+        let tail = "
+    jump loc_270                    # 02F4  1270
+: sub_2f6
+    i := data_314                   # 02F6  A314
+    bcd v5                          # 02F8  F533
+    load v2                         # 02FA  F265
+    i := hex v1                     # 02FC  F129
+    v3 := 0x37                      # 02FE  6337
+    v4 := 0x00                      # 0300  6400
+    sprite v3 v4 5                  # 0302  D345
+    v3 += 0x05                      # 0304  7305
+    i := hex v2                     # 0306  F229
+    sprite v3 v4 5                  # 0308  D345
+    return                          # 030A  00EE
+: data_30c
+    0xe0 0x00                       # 030C  E000
+: data_30e
+    0x80 0x00                       # 030E  8000
+: data_310
+    0xfc 0x00                       # 0310  FC00
+: data_312
+    0xaa 0x00                       # 0312  AA00
+: data_314
+    0x00 0x00 0x00 0x00             # 0314  00000000
+";
+        assert!(listing.starts_with(head), "{}", listing);
+        assert!(listing.ends_with(tail), "{}", listing);
+    }
+
+    #[test]
+    fn data_after_jump() {
+        // call a subroutine, point i at the bytes after an endless loop
+        let rom = [0x22, 0x08, 0xA2, 0x06, 0x12, 0x04, 0xFF, 0xFF, 0x00, 0xEE];
+        let expected = "\
+main:
+    CALL sub_208                    ; 0200  2208
+    LD I, data_206                  ; 0202  A206
+loc_204:
+    JP loc_204                      ; 0204  1204
+data_206:
+    db 0xff, 0xff                   ; 0206  FFFF
+sub_208:
+    RET                             ; 0208  00EE
+";
+        assert_eq!(disassemble(&rom, 0x200, Syntax::Classic), expected);
+    }
+}
@@ -1,122 +1,153 @@
-use macroquad::prelude::*;
-
-use crate::{postprocessing::GfxPipeline, shaders::Bloom}; // ChromaticAberration, GaussianBlur
-
-type GfxBuffer = [bool; 64 * 32];
+// one byte per pixel, bit n is set if the pixel is lit on plane n
+pub type GfxBuffer = [u8; 128 * 64];
 
 pub struct Display {
     pub buffer: GfxBuffer,
-    width_ratio: f32,
-    height_ratio: f32,
-    post_processing: GfxPipeline<1>, // never ended up using this
+    pub hires: bool, // SCHIP 128x64 mode, otherwise 64x32
+    pub planes: u8,  // XO-CHIP bit-planes selected for drawing, clearing and scrolling
 }
 
 impl Display {
-    pub const WIDTH: u8 = 64;
-    pub const HEIGHT: u8 = 32;
+    pub const LORES_WIDTH: u8 = 64;
+    pub const LORES_HEIGHT: u8 = 32;
+
+    pub const HIRES_WIDTH: u8 = 128;
+    pub const HIRES_HEIGHT: u8 = 64;
 
-    pub const WIDTH_F32: f32 = Self::WIDTH as f32;
-    pub const HEIGHT_F32: f32 = Self::HEIGHT as f32;
+    pub const LENGTH: usize = (Self::HIRES_WIDTH as usize) * (Self::HIRES_HEIGHT as usize);
 
-    pub const LENGTH: usize = (Self::WIDTH as usize) * (Self::HEIGHT as usize);
+    pub const PLANES: usize = 4;
 
-    pub const CLEAR: GfxBuffer = [false; Self::LENGTH];
+    pub const CLEAR: GfxBuffer = [0; Self::LENGTH];
 
     pub fn new() -> Self {
-        let width = screen_width();
-        let height = screen_height();
         Self {
             buffer: Self::CLEAR,
-            width_ratio: screen_width() / Self::WIDTH_F32,
-            height_ratio: screen_height() / Self::HEIGHT_F32,
-            post_processing: GfxPipeline::new(width, height, &[(*Bloom, true)]),
+            hires: false,
+            planes: 0b0001,
         }
     }
 
-    pub fn update_screen_size(&mut self) {
-        let width = screen_width();
-        let height = screen_height();
-
-        let new_wr = width / Self::WIDTH_F32;
-        let new_hr = height / Self::HEIGHT_F32;
+    #[inline(always)]
+    pub fn width(&self) -> u8 {
+        if self.hires {
+            Self::HIRES_WIDTH
+        } else {
+            Self::LORES_WIDTH
+        }
+    }
 
-        if new_wr != self.width_ratio || new_hr != self.height_ratio {
-            self.width_ratio = new_wr;
-            self.height_ratio = new_hr;
-            self.post_processing.update_dimensions(width, height);
+    #[inline(always)]
+    pub fn height(&self) -> u8 {
+        if self.hires {
+            Self::HIRES_HEIGHT
+        } else {
+            Self::LORES_HEIGHT
         }
     }
 
-    pub fn clear(&mut self) {
+    // switch between 64x32 and 128x64, every plane is cleared
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
         self.buffer = Self::CLEAR;
-        // for i in 0..Self::LENGTH {
-        //     self.buffer[i] = false;
-        // }
     }
 
-    #[inline(always)]
-    pub fn i(x: u8, y: u8) -> usize {
-        let mut x = x;
-        if x >= Self::WIDTH {
-            x -= Self::WIDTH;
+    // clear the selected planes
+    pub fn clear(&mut self) {
+        let keep = !self.planes;
+        for p in self.buffer.iter_mut() {
+            *p &= keep;
         }
+    }
 
-        let mut y = y;
-        if y >= Self::HEIGHT {
-            y -= Self::HEIGHT;
-        }
+    #[inline(always)]
+    pub fn i(&self, x: u8, y: u8) -> usize {
+        let x = x % self.width();
+        let y = y % self.height();
+        (x as usize) + (self.width() as usize) * (y as usize)
+    }
+
+    // palette index of a pixel, 0 is background
+    #[inline(always)]
+    pub fn pixel(&self, x: u8, y: u8) -> u8 {
+        self.buffer[self.i(x, y)]
+    }
 
-        (x as usize) + (Self::WIDTH as usize) * (y as usize)
+    #[inline(always)]
+    pub fn set_plane(&mut self, plane: usize, x: u8, y: u8, b: bool) {
+        let i = self.i(x, y);
+        if b {
+            self.buffer[i] |= 1 << plane;
+        } else {
+            self.buffer[i] &= !(1 << plane);
+        }
     }
 
     #[inline(always)]
-    pub fn set(&mut self, x: u8, y: u8, b: bool) {
-        self.buffer[Self::i(x, y)] = b
+    pub fn get_plane(&self, plane: usize, x: u8, y: u8) -> bool {
+        (self.pixel(x, y) >> plane) & 1 != 0
     }
 
+    // is the pixel lit on any plane
     #[inline(always)]
     pub fn get(&self, x: u8, y: u8) -> bool {
-        self.buffer[Self::i(x, y)]
+        self.pixel(x, y) != 0
     }
 
     pub fn get_u8(&self, x: u8, y: u8) -> u8 {
-        if self.buffer[Self::i(x, y)] {
-            1
-        } else {
-            0
-        }
+        self.get(x, y) as u8
     }
 
-    pub fn draw(&mut self, post: bool) {
-        self.update_screen_size();
-
-        const DRAW_COLOR: Color = Color {
-            r: 1.0,
-            g: 0.4,
-            b: 0.78823525,
-            a: 1.0,
-        };
-
-        let draw = &|| {
-            for y in 0..Self::HEIGHT {
-                for x in 0..Self::WIDTH {
-                    if self.get(x, y) {
-                        draw_rectangle(
-                            self.width_ratio * x as f32,
-                            self.height_ratio * y as f32,
-                            self.width_ratio as f32,
-                            self.height_ratio as f32,
-                            DRAW_COLOR,
-                        );
-                    }
-                }
-            }
-        };
+    // the selected planes, lowest first
+    pub fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..Self::PLANES).filter(move |p| (planes >> p) & 1 != 0)
+    }
 
-        if post {
-            self.post_processing.pipe(&draw);
-        } else {
-            draw()
+    // move the selected planes so that (x, y) takes the value of (x - dx, y - dy),
+    // pixels scrolled in from outside the screen are blank
+    fn scroll(&mut self, dx: i16, dy: i16) {
+        let (width, height) = (self.width() as i16, self.height() as i16);
+        let mask = self.planes;
+        let old = self.buffer;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let src = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    old[(sx + width * sy) as usize] & mask
+                } else {
+                    0
+                };
+                let i = (x + width * y) as usize;
+                self.buffer[i] = (old[i] & !mask) | src;
+            }
         }
     }
+
+    // scroll the screen down by n rows
+    pub fn scroll_down(&mut self, n: u8) {
+        self.scroll(0, n as i16);
+    }
+
+    // scroll the screen up by n rows
+    pub fn scroll_up(&mut self, n: u8) {
+        self.scroll(0, -(n as i16));
+    }
+
+    // scroll the screen right by n columns
+    pub fn scroll_right(&mut self, n: u8) {
+        self.scroll(n as i16, 0);
+    }
+
+    // scroll the screen left by n columns
+    pub fn scroll_left(&mut self, n: u8) {
+        self.scroll(-(n as i16), 0);
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
 }
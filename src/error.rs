@@ -0,0 +1,59 @@
+use std::fmt;
+
+// Faults a ROM can cause. `pc` is the address of the offending instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmulatorError {
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    OutOfBounds { pc: u16, addr: u32 },
+    IllegalOpcode { pc: u16, opcode: u16 },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::StackOverflow { pc } => write!(f, "stack overflow at {:#06X}", pc),
+            Self::StackUnderflow { pc } => write!(f, "stack underflow at {:#06X}", pc),
+            Self::OutOfBounds { pc, addr } => {
+                write!(f, "out of bounds access to {:#07X} at {:#06X}", addr, pc)
+            }
+            Self::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:#06X} at {:#06X}", opcode, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+// Reasons a ROM can't be loaded, see `Chip8::load_rom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomError {
+    Empty,
+    TooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Empty => write!(f, "ROM is empty"),
+            Self::TooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, at most {} fit in memory", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+// What to do when a ROM faults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    // stop the machine and report the error
+    #[default]
+    Halt,
+    // wrap stack pointers and addresses around, skip illegal opcodes
+    Wrap,
+    // drop the faulting access (reads give 0), skip illegal opcodes
+    Ignore,
+}
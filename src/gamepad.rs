@@ -0,0 +1,53 @@
+use chirp::input::Input;
+use gilrs::{Button, Gilrs};
+
+// gamepad buttons and the chip8 keys they press, most games steer with
+// 2/4/6/8 and use 5 as the action key
+const BUTTON_MAP: [(Button, u8); 8] = [
+    (Button::DPadUp, 0x2),
+    (Button::DPadLeft, 0x4),
+    (Button::DPadRight, 0x6),
+    (Button::DPadDown, 0x8),
+    (Button::South, 0x5),
+    (Button::East, 0x6),
+    (Button::West, 0x4),
+    (Button::North, 0x2),
+];
+
+pub struct Gamepad {
+    gilrs: Option<Gilrs>, // None if no gamepad backend is available
+    keys: u16,            // keys held as of the last poll
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        Self {
+            gilrs: Gilrs::new().ok(),
+            keys: 0,
+        }
+    }
+}
+
+impl Input for Gamepad {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.keys.is_key_down(key)
+    }
+
+    fn poll(&mut self) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+
+        // drain events so gilrs updates its cached button state
+        while gilrs.next_event().is_some() {}
+
+        self.keys = 0;
+        for (_, pad) in gilrs.gamepads() {
+            for (button, key) in BUTTON_MAP {
+                if pad.is_pressed(button) {
+                    self.keys |= 1 << key;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Input backends for the chip8 keypad.
+//
+// The core never polls a keyboard itself; `KeyPad` asks an `Input` whether a
+// key (0x0-0xF) is held. Frontends provide their own implementations
+// (keyboard, gamepad, web page), and `Scripted` lets tests and replays feed
+// key state deterministically.
+
+pub trait Input: Send {
+    // is chip8 key `key` (0x0-0xF) currently held
+    fn is_key_down(&self, key: u8) -> bool;
+
+    // called once per frame before the frame's cycles are run
+    fn poll(&mut self) {}
+}
+
+// no keys are ever pressed
+pub struct NoInput;
+
+impl Input for NoInput {
+    fn is_key_down(&self, _key: u8) -> bool {
+        false
+    }
+}
+
+// a fixed set of held keys, bit n is key n
+impl Input for u16 {
+    fn is_key_down(&self, key: u8) -> bool {
+        (self >> key) & 1 != 0
+    }
+}
+
+// either backend can hold a key, e.g. keyboard + on-screen keypad
+impl<A: Input, B: Input> Input for (A, B) {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.0.is_key_down(key) || self.1.is_key_down(key)
+    }
+
+    fn poll(&mut self) {
+        self.0.poll();
+        self.1.poll();
+    }
+}
+
+// A scripted sequence of per-frame key states.
+//
+// let script = Scripted::new().wait(60).hold(&[0x5], 10).wait(30);
+//
+// Once the script runs out no keys are held.
+#[derive(Clone, Default)]
+pub struct Scripted {
+    frames: Vec<u16>,      // key bitmask for each frame
+    cursor: Option<usize>, // current frame, None before the first poll
+}
+
+impl Scripted {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_frames(frames: Vec<u16>) -> Self {
+        Self {
+            frames,
+            cursor: None,
+        }
+    }
+
+    // hold `keys` for `frames` frames
+    pub fn hold(mut self, keys: &[u8], frames: usize) -> Self {
+        let mask = keys.iter().fold(0u16, |mask, &k| mask | (1 << (k & 0xF)));
+        self.frames.extend(std::iter::repeat_n(mask, frames));
+        self
+    }
+
+    // hold nothing for `frames` frames
+    pub fn wait(self, frames: usize) -> Self {
+        self.hold(&[], frames)
+    }
+
+    // a script written as comma separated FRAMES:KEYS steps, where KEYS are
+    // hex digits held together or - for none, e.g. "60:-,10:5,30:-,5:12"
+    pub fn parse(script: &str) -> Option<Self> {
+        script.split(',').try_fold(Self::new(), |script, step| {
+            let (frames, keys) = step.trim().split_once(':')?;
+            let frames = frames.parse().ok()?;
+            let keys = match keys {
+                "-" => Vec::new(),
+                keys => keys
+                    .chars()
+                    .map(|k| k.to_digit(16).map(|k| k as u8))
+                    .collect::<Option<_>>()?,
+            };
+            Some(script.hold(&keys, frames))
+        })
+    }
+
+    pub fn finished(&self) -> bool {
+        self.cursor.is_some_and(|c| c >= self.frames.len())
+    }
+
+    fn current(&self) -> u16 {
+        self.cursor
+            .and_then(|c| self.frames.get(c))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Input for Scripted {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.current().is_key_down(key)
+    }
+
+    fn poll(&mut self) {
+        self.cursor = Some(self.cursor.map_or(0, |c| c + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the keys held in each frame until the script finishes
+    fn run(mut input: Scripted) -> Vec<u16> {
+        let mut frames = Vec::new();
+        loop {
+            input.poll();
+            if input.finished() {
+                return frames;
+            }
+            frames.push(
+                (0..16)
+                    .filter(|&k| input.is_key_down(k))
+                    .fold(0, |m, k| m | 1 << k),
+            );
+        }
+    }
+
+    #[test]
+    fn hold_and_wait() {
+        let input = Scripted::new()
+            .wait(2)
+            .hold(&[0x5, 0xA], 1)
+            .hold(&[0x15], 2);
+        // nothing is held before the first poll
+        assert!(!input.is_key_down(0x5));
+        assert!(!input.finished());
+        assert_eq!(run(input), [0, 0, 0x0420, 0x0020, 0x0020]);
+    }
+
+    #[test]
+    fn finished() {
+        let mut input = Scripted::new().hold(&[1], 1);
+        input.poll();
+        assert!(input.is_key_down(1));
+        assert!(!input.finished());
+        input.poll();
+        assert!(input.finished());
+        assert!(!input.is_key_down(1));
+
+        let mut empty = Scripted::new();
+        assert!(!empty.finished());
+        empty.poll();
+        assert!(empty.finished());
+    }
+
+    #[test]
+    fn parse() {
+        let script = Scripted::parse("2:-, 1:5a,1:F").unwrap();
+        assert_eq!(run(script), [0, 0, 0x0420, 0x8000]);
+        assert_eq!(run(Scripted::parse("0:1").unwrap()), []);
+
+        for bad in ["", "10", "x:5", "10:g", "-1:5", "10:5,", "10:5;2:3"] {
+            assert!(Scripted::parse(bad).is_none(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn either_input() {
+        let mut input = (Scripted::new().hold(&[2], 1), 0b1001u16);
+        assert!(input.is_key_down(0) && input.is_key_down(3));
+        assert!(!input.is_key_down(2));
+        // polling the pair advances the script
+        input.poll();
+        assert!(input.is_key_down(2) && input.is_key_down(3));
+        assert!(!input.is_key_down(1));
+        assert!(!(NoInput, NoInput).is_key_down(0));
+    }
+}
@@ -0,0 +1,425 @@
+// Decoded instructions.
+//
+// `decode` is the one place opcodes are taken apart; the interpreter, the
+// disassembler and the debugger all work on `Instruction`. `encode` turns one
+// back into the same opcode, and `Display` prints the classic mnemonic the
+// assembler reads. F000 is the only instruction longer than one word, its
+// operand is only known with `decode_long`.
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Idle,                         // 0000 loop in place
+    ScrollDown { n: u8 },         // 00CN scroll down n rows
+    ScrollUp { n: u8 },           // 00DN scroll up n rows
+    Clear,                        // 00E0 clear screen
+    Return,                       // 00EE return from subroutine
+    ScrollRight,                  // 00FB scroll right 4 pixels
+    ScrollLeft,                   // 00FC scroll left 4 pixels
+    Exit,                         // 00FD exit interpreter
+    LoRes,                        // 00FE low resolution
+    HiRes,                        // 00FF high resolution
+    Jump { nnn: u16 },            // 1NNN jump
+    Call { nnn: u16 },            // 2NNN call subroutine at nnn
+    SkipEqImm { x: u8, nn: u8 },  // 3XNN skip if Vx == nn
+    SkipNeImm { x: u8, nn: u8 },  // 4XNN skip if Vx != nn
+    SkipEq { x: u8, y: u8 },      // 5XY0 skip if Vx == Vy
+    SaveRange { x: u8, y: u8 },   // 5XY2 store Vx..Vy to memory
+    LoadRange { x: u8, y: u8 },   // 5XY3 load memory to Vx..Vy
+    SetImm { x: u8, nn: u8 },     // 6XNN set register Vx to nn
+    AddImm { x: u8, nn: u8 },     // 7XNN add value to register Vx
+    Set { x: u8, y: u8 },         // 8XY0 set Vx to Vy
+    Or { x: u8, y: u8 },          // 8XY1 set Vx to Vx | Vy
+    And { x: u8, y: u8 },         // 8XY2 set Vx to Vx & Vy
+    Xor { x: u8, y: u8 },         // 8XY3 set Vx to Vx ^ Vy
+    Add { x: u8, y: u8 },         // 8XY4 add Vy to Vx
+    Sub { x: u8, y: u8 },         // 8XY5 set Vx to Vx - Vy
+    ShiftRight { x: u8, y: u8 },  // 8XY6 shift right Vx by 1
+    SubReverse { x: u8, y: u8 },  // 8XY7 set Vx to Vy - Vx
+    ShiftLeft { x: u8, y: u8 },   // 8XYE shift left Vx by 1
+    SkipNe { x: u8, y: u8 },      // 9XY0 skip if Vx != Vy
+    SetI { nnn: u16 },            // ANNN set index to register I
+    JumpOffset { nnn: u16 },      // BNNN jump to v0 + nnn
+    Random { x: u8, nn: u8 },     // CXNN set Vx to a random number & nn
+    DrawBig { x: u8, y: u8 },     // DXY0 draw 16x16 sprite
+    Draw { x: u8, y: u8, n: u8 }, // DXYN display/draw
+    SkipKey { x: u8 },            // EX9E skip if key down
+    SkipNotKey { x: u8 },         // EXA1 skip if key not down
+    SetILong { nnnn: u16 },       // F000 NNNN set I to the next 16 bit word
+    Plane { n: u8 },              // FN01 select drawing planes
+    Audio,                        // F002 load audio pattern
+    GetDelay { x: u8 },           // FX07 set Vx to value of delay timer
+    WaitKey { x: u8 },            // FX0A get key
+    SetDelay { x: u8 },           // FX15 set delay timer to value of Vx
+    SetSound { x: u8 },           // FX18 set sound timer to value of Vx
+    AddI { x: u8 },               // FX1E add Vx to I
+    Font { x: u8 },               // FX29 set I to be the font in Vx
+    BigFont { x: u8 },            // FX30 set I to be the big font in Vx
+    Bcd { x: u8 },                // FX33 get each number place and store in memory
+    Pitch { x: u8 },              // FX3A set audio pitch
+    Store { x: u8 },              // FX55 store registers to memory
+    Load { x: u8 },               // FX65 load memory to registers
+    SaveFlags { x: u8 },          // FX75 store registers to rpl flags
+    LoadFlags { x: u8 },          // FX85 load rpl flags to registers
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownOpcode(pub u16);
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOpcode {}
+
+// like `decode`, with `next` as the operand of F000
+pub fn decode_long(opcode: u16, next: u16) -> Result<Instruction, UnknownOpcode> {
+    Ok(match decode(opcode)? {
+        Instruction::SetILong { .. } => Instruction::SetILong { nnnn: next },
+        instruction => instruction,
+    })
+}
+
+// a single word, F000 decodes with an operand of 0
+pub fn decode(opcode: u16) -> Result<Instruction, UnknownOpcode> {
+    use Instruction::*;
+
+    let i = ((opcode & 0xF000) >> 12) as u8;
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    Ok(match (i, x, y, n) {
+        (0x00, 0x00, 0x00, 0x00) => Idle,
+
+        (0x00, 0x00, 0x0C, _) => ScrollDown { n },
+        (0x00, 0x00, 0x0D, _) => ScrollUp { n },
+        (0x00, 0x00, 0x0E, 0x00) => Clear,
+        (0x00, 0x00, 0x0E, 0x0E) => Return,
+        (0x00, 0x00, 0x0F, 0x0B) => ScrollRight,
+        (0x00, 0x00, 0x0F, 0x0C) => ScrollLeft,
+        (0x00, 0x00, 0x0F, 0x0D) => Exit,
+        (0x00, 0x00, 0x0F, 0x0E) => LoRes,
+        (0x00, 0x00, 0x0F, 0x0F) => HiRes,
+
+        (0x01, _, _, _) => Jump { nnn },
+        (0x02, _, _, _) => Call { nnn },
+        (0x03, _, _, _) => SkipEqImm { x, nn },
+        (0x04, _, _, _) => SkipNeImm { x, nn },
+        (0x05, _, _, 0x00) => SkipEq { x, y },
+        (0x05, _, _, 0x02) => SaveRange { x, y },
+        (0x05, _, _, 0x03) => LoadRange { x, y },
+        (0x06, _, _, _) => SetImm { x, nn },
+        (0x07, _, _, _) => AddImm { x, nn },
+
+        (0x08, _, _, 0x00) => Set { x, y },
+        (0x08, _, _, 0x01) => Or { x, y },
+        (0x08, _, _, 0x02) => And { x, y },
+        (0x08, _, _, 0x03) => Xor { x, y },
+        (0x08, _, _, 0x04) => Add { x, y },
+        (0x08, _, _, 0x05) => Sub { x, y },
+        (0x08, _, _, 0x06) => ShiftRight { x, y },
+        (0x08, _, _, 0x07) => SubReverse { x, y },
+        (0x08, _, _, 0x0E) => ShiftLeft { x, y },
+
+        (0x09, _, _, 0x00) => SkipNe { x, y },
+
+        (0x0A, _, _, _) => SetI { nnn },
+        (0x0B, _, _, _) => JumpOffset { nnn },
+        (0x0C, _, _, _) => Random { x, nn },
+        (0x0D, _, _, 0x00) => DrawBig { x, y },
+        (0x0D, _, _, _) => Draw { x, y, n },
+
+        (0x0E, _, 0x09, 0x0E) => SkipKey { x },
+        (0x0E, _, 0x0A, 0x01) => SkipNotKey { x },
+
+        (0x0F, 0x00, 0x00, 0x00) => SetILong { nnnn: 0 },
+        (0x0F, _, 0x00, 0x01) => Plane { n: x },
+        (0x0F, 0x00, 0x00, 0x02) => Audio,
+        (0x0F, _, 0x00, 0x07) => GetDelay { x },
+        (0x0F, _, 0x00, 0x0A) => WaitKey { x },
+        (0x0F, _, 0x01, 0x05) => SetDelay { x },
+        (0x0F, _, 0x01, 0x08) => SetSound { x },
+        (0x0F, _, 0x01, 0x0E) => AddI { x },
+        (0x0F, _, 0x02, 0x09) => Font { x },
+        (0x0F, _, 0x03, 0x00) => BigFont { x },
+        (0x0F, _, 0x03, 0x03) => Bcd { x },
+        (0x0F, _, 0x03, 0x0A) => Pitch { x },
+        (0x0F, _, 0x05, 0x05) => Store { x },
+        (0x0F, _, 0x06, 0x05) => Load { x },
+        (0x0F, _, 0x07, 0x05) => SaveFlags { x },
+        (0x0F, _, 0x08, 0x05) => LoadFlags { x },
+
+        _ => return Err(UnknownOpcode(opcode)),
+    })
+}
+
+impl Instruction {
+    // the opcode `decode` reads this from, operands are masked to their nibbles
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let op = |base: u16, x: u8, y: u8, n: u8| {
+            base | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | (n as u16 & 0xF)
+        };
+        let xnn = |base: u16, x: u8, nn: u8| base | ((x as u16 & 0xF) << 8) | nn as u16;
+
+        match *self {
+            Idle => 0x0000,
+            ScrollDown { n } => op(0x00C0, 0, 0, n),
+            ScrollUp { n } => op(0x00D0, 0, 0, n),
+            Clear => 0x00E0,
+            Return => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LoRes => 0x00FE,
+            HiRes => 0x00FF,
+            Jump { nnn } => 0x1000 | (nnn & 0xFFF),
+            Call { nnn } => 0x2000 | (nnn & 0xFFF),
+            SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            SkipEq { x, y } => op(0x5000, x, y, 0),
+            SaveRange { x, y } => op(0x5000, x, y, 2),
+            LoadRange { x, y } => op(0x5000, x, y, 3),
+            SetImm { x, nn } => xnn(0x6000, x, nn),
+            AddImm { x, nn } => xnn(0x7000, x, nn),
+            Set { x, y } => op(0x8000, x, y, 0),
+            Or { x, y } => op(0x8000, x, y, 1),
+            And { x, y } => op(0x8000, x, y, 2),
+            Xor { x, y } => op(0x8000, x, y, 3),
+            Add { x, y } => op(0x8000, x, y, 4),
+            Sub { x, y } => op(0x8000, x, y, 5),
+            ShiftRight { x, y } => op(0x8000, x, y, 6),
+            SubReverse { x, y } => op(0x8000, x, y, 7),
+            ShiftLeft { x, y } => op(0x8000, x, y, 0xE),
+            SkipNe { x, y } => op(0x9000, x, y, 0),
+            SetI { nnn } => 0xA000 | (nnn & 0xFFF),
+            JumpOffset { nnn } => 0xB000 | (nnn & 0xFFF),
+            Random { x, nn } => xnn(0xC000, x, nn),
+            DrawBig { x, y } => op(0xD000, x, y, 0),
+            Draw { x, y, n } => op(0xD000, x, y, n),
+            SkipKey { x } => op(0xE09E, x, 0, 0),
+            SkipNotKey { x } => op(0xE0A1, x, 0, 0),
+            SetILong { .. } => 0xF000,
+            Plane { n } => op(0xF001, n, 0, 0),
+            Audio => 0xF002,
+            GetDelay { x } => op(0xF007, x, 0, 0),
+            WaitKey { x } => op(0xF00A, x, 0, 0),
+            SetDelay { x } => op(0xF015, x, 0, 0),
+            SetSound { x } => op(0xF018, x, 0, 0),
+            AddI { x } => op(0xF01E, x, 0, 0),
+            Font { x } => op(0xF029, x, 0, 0),
+            BigFont { x } => op(0xF030, x, 0, 0),
+            Bcd { x } => op(0xF033, x, 0, 0),
+            Pitch { x } => op(0xF03A, x, 0, 0),
+            Store { x } => op(0xF055, x, 0, 0),
+            Load { x } => op(0xF065, x, 0, 0),
+            SaveFlags { x } => op(0xF075, x, 0, 0),
+            LoadFlags { x } => op(0xF085, x, 0, 0),
+        }
+    }
+
+    // bytes taken in memory, F000 is followed by its 16 bit operand
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::SetILong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            SkipEqImm { .. }
+                | SkipNeImm { .. }
+                | SkipEq { .. }
+                | SkipNe { .. }
+                | SkipKey { .. }
+                | SkipNotKey { .. }
+        )
+    }
+
+    // classic mnemonic, `name` formats addresses so listings can show labels
+    pub fn classic(&self, name: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+
+        match *self {
+            Idle => "db 0x00, 0x00".to_owned(),
+            ScrollDown { n } => format!("SCD {}", n),
+            ScrollUp { n } => format!("SCU {}", n),
+            Clear => "CLS".to_owned(),
+            Return => "RET".to_owned(),
+            ScrollRight => "SCR".to_owned(),
+            ScrollLeft => "SCL".to_owned(),
+            Exit => "EXIT".to_owned(),
+            LoRes => "LOW".to_owned(),
+            HiRes => "HIGH".to_owned(),
+            Jump { nnn } => format!("JP {}", name(nnn)),
+            Call { nnn } => format!("CALL {}", name(nnn)),
+            SkipEqImm { x, nn } => format!("SE V{:X}, {:#04x}", x, nn),
+            SkipNeImm { x, nn } => format!("SNE V{:X}, {:#04x}", x, nn),
+            SkipEq { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            LoadRange { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            SetImm { x, nn } => format!("LD V{:X}, {:#04x}", x, nn),
+            AddImm { x, nn } => format!("ADD V{:X}, {:#04x}", x, nn),
+            Set { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            SubReverse { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SkipNe { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            SetI { nnn } => format!("LD I, {}", name(nnn)),
+            JumpOffset { nnn } => format!("JP V0, {}", name(nnn)),
+            Random { x, nn } => format!("RND V{:X}, {:#04x}", x, nn),
+            DrawBig { x, y } => format!("DRW V{:X}, V{:X}, 0", x, y),
+            Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey { x } => format!("SKP V{:X}", x),
+            SkipNotKey { x } => format!("SKNP V{:X}", x),
+            SetILong { nnnn } => format!("LD I, LONG {}", name(nnnn)),
+            Plane { n } => format!("PLANE {}", n),
+            Audio => "AUDIO".to_owned(),
+            GetDelay { x } => format!("LD V{:X}, DT", x),
+            WaitKey { x } => format!("LD V{:X}, K", x),
+            SetDelay { x } => format!("LD DT, V{:X}", x),
+            SetSound { x } => format!("LD ST, V{:X}", x),
+            AddI { x } => format!("ADD I, V{:X}", x),
+            Font { x } => format!("LD F, V{:X}", x),
+            BigFont { x } => format!("LD HF, V{:X}", x),
+            Bcd { x } => format!("LD B, V{:X}", x),
+            Pitch { x } => format!("PITCH V{:X}", x),
+            Store { x } => format!("LD [I], V{:X}", x),
+            Load { x } => format!("LD V{:X}, [I]", x),
+            SaveFlags { x } => format!("LD R, V{:X}", x),
+            LoadFlags { x } => format!("LD V{:X}, R", x),
+        }
+    }
+
+    // Octo mnemonic, `name` as for `classic`
+    pub fn octo(&self, name: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+
+        match *self {
+            Idle => "0x00 0x00".to_owned(),
+            ScrollDown { n } => format!("scroll-down {}", n),
+            ScrollUp { n } => format!("scroll-up {}", n),
+            Clear => "clear".to_owned(),
+            Return => "return".to_owned(),
+            ScrollRight => "scroll-right".to_owned(),
+            ScrollLeft => "scroll-left".to_owned(),
+            Exit => "exit".to_owned(),
+            LoRes => "lores".to_owned(),
+            HiRes => "hires".to_owned(),
+            Jump { nnn } => format!("jump {}", name(nnn)),
+            Call { nnn } => format!(":call {}", name(nnn)),
+            SkipEqImm { x, nn } => format!("if v{:x} != {:#04x} then", x, nn),
+            SkipNeImm { x, nn } => format!("if v{:x} == {:#04x} then", x, nn),
+            SkipEq { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            SetImm { x, nn } => format!("v{:x} := {:#04x}", x, nn),
+            AddImm { x, nn } => format!("v{:x} += {:#04x}", x, nn),
+            Set { x, y } => format!("v{:x} := v{:x}", x, y),
+            Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            SubReverse { x, y } => format!("v{:x} =- v{:x}", x, y),
+            ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            SkipNe { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            SetI { nnn } => format!("i := {}", name(nnn)),
+            JumpOffset { nnn } => format!("jump0 {}", name(nnn)),
+            Random { x, nn } => format!("v{:x} := random {:#04x}", x, nn),
+            DrawBig { x, y } => format!("sprite v{:x} v{:x} 0", x, y),
+            Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            SkipKey { x } => format!("if v{:x} -key then", x),
+            SkipNotKey { x } => format!("if v{:x} key then", x),
+            SetILong { nnnn } => format!("i := long {}", name(nnnn)),
+            Plane { n } => format!("plane {}", n),
+            Audio => "audio".to_owned(),
+            GetDelay { x } => format!("v{:x} := delay", x),
+            WaitKey { x } => format!("v{:x} := key", x),
+            SetDelay { x } => format!("delay := v{:x}", x),
+            SetSound { x } => format!("buzzer := v{:x}", x),
+            AddI { x } => format!("i += v{:x}", x),
+            Font { x } => format!("i := hex v{:x}", x),
+            BigFont { x } => format!("i := bighex v{:x}", x),
+            Bcd { x } => format!("bcd v{:x}", x),
+            Pitch { x } => format!("pitch := v{:x}", x),
+            Store { x } => format!("save v{:x}", x),
+            Load { x } => format!("load v{:x}", x),
+            SaveFlags { x } => format!("saveflags v{:x}", x),
+            LoadFlags { x } => format!("loadflags v{:x}", x),
+        }
+    }
+}
+
+// classic syntax with numeric addresses
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.classic(&|addr| format!("{:#05x}", addr));
+        write!(f, "{}", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    // the bytes `instruction` takes in memory
+    fn bytes(instruction: &Instruction) -> Vec<u8> {
+        let mut bytes = instruction.encode().to_be_bytes().to_vec();
+        if let Instruction::SetILong { nnnn } = instruction {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut known = 0;
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+                known += 1;
+            }
+        }
+        // all of 1NNN-4XNN, 6XNN, 7XNN and ANNN-DXYN at least
+        assert!(known > 10 * 0x1000, "{} opcodes decode", known);
+    }
+
+    #[test]
+    fn display_reassembles() {
+        let long = decode_long(0xF000, 0xBEEF).unwrap();
+        assert_eq!(long.to_string(), "LD I, LONG 0xbeef");
+        let instructions = (0..=0xFFFF)
+            .filter_map(|opcode| decode(opcode).ok())
+            .chain([long]);
+        for instruction in instructions {
+            let text = instruction.to_string();
+            let program = assemble(&text).unwrap_or_else(|e| panic!("{}: {:?}", text, e));
+            assert_eq!(program.bytes, bytes(&instruction), "{}", text);
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        for opcode in [0x0001, 0x5001, 0x8008, 0x9001, 0xE000, 0xF0FF] {
+            assert_eq!(decode(opcode), Err(UnknownOpcode(opcode)));
+        }
+        assert_eq!(decode_long(0xF0FF, 0), Err(UnknownOpcode(0xF0FF)));
+    }
+}
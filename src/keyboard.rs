@@ -0,0 +1,34 @@
+use chirp::input::Input;
+use macroquad::prelude::{is_key_down, KeyCode};
+
+// QWERTY layout of the COSMAC VIP hex keypad
+//   1 2 3 4
+//   Q W E R
+//   A S D F
+//   Z X C V
+const KEYPAD_MAP: [KeyCode; 16] = [
+    KeyCode::X,    // 0x0
+    KeyCode::Key1, // 0x1
+    KeyCode::Key2, // 0x2
+    KeyCode::Key3, // 0x3
+    KeyCode::Q,    // 0x4
+    KeyCode::W,    // 0x5
+    KeyCode::E,    // 0x6
+    KeyCode::A,    // 0x7
+    KeyCode::S,    // 0x8
+    KeyCode::D,    // 0x9
+    KeyCode::Z,    // 0xA
+    KeyCode::C,    // 0xB
+    KeyCode::Key4, // 0xC
+    KeyCode::R,    // 0xD
+    KeyCode::F,    // 0xE
+    KeyCode::V,    // 0xF
+];
+
+pub struct Keyboard;
+
+impl Input for Keyboard {
+    fn is_key_down(&self, key: u8) -> bool {
+        is_key_down(KEYPAD_MAP[key as usize])
+    }
+}
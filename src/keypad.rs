@@ -1,8 +1,9 @@
-use macroquad::prelude::{is_key_down, KeyCode};
+use crate::input::{Input, NoInput};
 
 pub struct KeyPad {
     pub waiting: bool,        // is the keypad waiting for an input
     pub dest_register: usize, // destination v register
+    input: Box<dyn Input>,    // where key state comes from
 }
 
 impl KeyPad {
@@ -10,32 +11,33 @@ impl KeyPad {
         Self {
             waiting: false,
             dest_register: 0,
+            input: Box::new(NoInput),
         }
     }
 
-    const KEYPAD_MAP: [(KeyCode, u8); 16] = [
-        (KeyCode::X, 0x00),
-        (KeyCode::Key1, 0x01),
-        (KeyCode::Key2, 0x02),
-        (KeyCode::Key3, 0x03),
-        (KeyCode::Q, 0x04),
-        (KeyCode::W, 0x05),
-        (KeyCode::E, 0x06),
-        (KeyCode::A, 0x07),
-        (KeyCode::S, 0x08),
-        (KeyCode::D, 0x09),
-        (KeyCode::Z, 0x0A),
-        (KeyCode::C, 0x0B),
-        (KeyCode::Key4, 0x0C),
-        (KeyCode::R, 0x0D),
-        (KeyCode::F, 0x0E),
-        (KeyCode::V, 0x0F),
-    ];
+    pub fn set_input(&mut self, input: impl Input + 'static) {
+        self.input = Box::new(input);
+    }
+
+    pub fn input(&self) -> &dyn Input {
+        self.input.as_ref()
+    }
+
+    // clears the wait state, the input backend is kept
+    pub fn reset(&mut self) {
+        self.waiting = false;
+        self.dest_register = 0;
+    }
+
+    pub fn poll(&mut self) {
+        self.input.poll();
+    }
+
     pub fn get_key(&mut self) -> Option<(u8, usize)> {
-        for (key_code, value) in Self::KEYPAD_MAP {
-            if is_key_down(key_code) {
+        for key in 0x0..=0xF {
+            if self.input.is_key_down(key) {
                 self.waiting = false;
-                return Some((value, self.dest_register));
+                return Some((key, self.dest_register));
             }
         }
         None
@@ -46,7 +48,14 @@ impl KeyPad {
         self.dest_register = dest;
     }
 
+    // only the low nibble of Vx names a key, as on the VIP
     pub fn is_key_down(&self, x: u8) -> bool {
-        is_key_down(Self::KEYPAD_MAP[x as usize].0)
+        self.input.is_key_down(x & 0xF)
+    }
+}
+
+impl Default for KeyPad {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,29 @@
+// Headless Chip-8 core.
+//
+// Nothing in here touches a window, GL context or audio device, so a `Chip8`
+// can be driven from unit tests, CI or server-side tools. The macroquad
+// frontend (see `main.rs`) is just one consumer of this crate.
+
+pub mod assembler;
+pub mod audio;
+pub mod chip8;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod error;
+pub mod input;
+pub mod instruction;
+pub mod keypad;
+pub mod memory;
+pub mod movie;
+pub mod opcodes;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod roms;
+pub mod savestate;
+pub mod scheduler;
+pub mod snapshot;
+pub mod stack;
+pub mod timing;
+pub mod trace;
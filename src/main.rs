@@ -4,22 +4,73 @@ extern crate lazy_static;
 use macroquad::prelude::*;
 
 #[cfg(not(target_arch = "wasm32"))]
-use chip8::Chip8;
+use chirp::{audio::Beeper, chip8::Chip8, debugger::Debugger, movie::Movie};
+use chirp::{rewind::Rewind, scheduler::Scheduler};
 #[cfg(not(target_arch = "wasm32"))]
-use roms::get_rom;
+use keyboard::Keyboard;
+#[cfg(not(target_arch = "wasm32"))]
+use movies::Movies;
+use renderer::Renderer;
+#[cfg(not(target_arch = "wasm32"))]
+use theme::Theme;
 #[cfg(target_arch = "wasm32")]
-use wasm::{BLOOM, CHIP};
-
-pub mod chip8;
-pub mod display;
-pub mod keypad;
-pub mod memory;
-pub mod opcodes;
-pub mod postprocessing;
-pub mod roms;
-pub mod shaders;
-pub mod stack;
-pub mod wasm;
+use wasm::{WebSpeaker, BLOOM, CHIP, DEBUGGER, MOVIES, SPEED, THEME};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod debugview;
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+mod gamepad;
+mod keyboard;
+mod movies;
+mod postprocessing;
+mod renderer;
+mod shaders;
+#[cfg(not(target_arch = "wasm32"))]
+mod slots;
+#[cfg(not(target_arch = "wasm32"))]
+mod sound;
+mod theme;
+mod wasm;
+
+// hold to step gameplay backwards
+const REWIND_KEY: KeyCode = KeyCode::Backspace;
+
+// hold to run FAST_FORWARD times faster
+const FAST_FORWARD_KEY: KeyCode = KeyCode::Tab;
+const FAST_FORWARD: f64 = 4.0;
+
+// the scheduler speed for this frame
+fn frame_speed(base: f64) -> f64 {
+    if is_key_down(FAST_FORWARD_KEY) {
+        base * FAST_FORWARD
+    } else {
+        base
+    }
+}
+
+// next palette and next pixel style
+#[cfg(not(target_arch = "wasm32"))]
+const THEME_KEY: KeyCode = KeyCode::F2;
+#[cfg(not(target_arch = "wasm32"))]
+const PIXEL_STYLE_KEY: KeyCode = KeyCode::F3;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_theme(theme: &Theme) {
+    if let Err(e) = theme.save() {
+        eprintln!("chirp: {}", e);
+    }
+}
+
+// ~20 seconds of history at 60fps
+fn rewind_buffer() -> Rewind {
+    Rewind::new(600, 2)
+}
+
+// a different CXNN sequence each launch, unless a seed is given
+fn clock_seed() -> u64 {
+    (macroquad::miniquad::date::now() * 1000.0) as u64
+}
 
 fn window_conf() -> Conf {
     Conf {
@@ -35,36 +86,210 @@ fn window_conf() -> Conf {
 async fn main() {
     {
         let mut chippy = CHIP.lock().unwrap();
-        chippy.load_font(0x00);
-        chippy.load(0x200, include_bytes!("roms/brix.ch8").to_vec());
+        chippy.reseed(clock_seed());
     }
+    wasm::start_rom(include_bytes!("roms/brix.ch8"));
+    wasm::restore_theme();
 
-    loop {
-        let fps = get_fps();
+    let mut renderer = Renderer::new();
+    let mut rewind = rewind_buffer();
+    let mut speaker = WebSpeaker::new();
+    let mut scheduler = Scheduler::new();
 
+    loop {
         unsafe {
             let mut chippy = CHIP.lock().unwrap();
-            chippy.sync_cycle(fps);
-            chippy.display.draw(BLOOM);
+            let mut debugger = DEBUGGER.lock().unwrap();
+            let mut movies = MOVIES.lock().unwrap();
+            scheduler.set_speed(frame_speed(SPEED));
+            let frames = scheduler.frames(get_frame_time() as f64);
+            debugview::update(&mut debugger, &mut chippy);
+            if is_key_down(REWIND_KEY) && !movies.active() {
+                rewind.rewind(&mut chippy);
+            } else {
+                for _ in 0..frames {
+                    if debugger.paused {
+                        break;
+                    }
+                    // only whole frames count, a breakpoint can stop one early
+                    match debugger.run_frame(&mut chippy) {
+                        Ok(true) => {
+                            rewind.record(&chippy);
+                            if let Some(status) = movies.frame_done(&chippy) {
+                                wasm::live_input(&mut chippy);
+                                wasm::report_message(&status);
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(e) => wasm::show_error(&e),
+                    }
+                }
+            }
+            speaker.update(&chippy);
+            wasm::send_state(&mut chippy); // for front-end
+            wasm::send_debug(&debugger, &chippy);
+            renderer.set_theme(&THEME.lock().unwrap());
+            renderer.draw(&chippy.display, BLOOM);
+            debugview::draw(&debugger, &chippy);
         }
         next_frame().await;
     }
 }
 
+// subcommands like `chirp disasm` run without a window
 #[cfg(not(target_arch = "wasm32"))]
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut chippy = Chip8::init(500);
-    chippy.load_font(0x00);
-    chippy.load(0x200, get_rom(&String::from("brix")).to_vec());
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match cli::run(&args) {
+        Some(result) => result,
+        None => cli::Options::parse(&args).map(|options| {
+            let conf = Conf {
+                window_width: 64 * options.scale as i32,
+                window_height: 32 * options.scale as i32,
+                fullscreen: options.fullscreen,
+                ..window_conf()
+            };
+            macroquad::Window::from_config(conf, run(options));
+        }),
+    };
+    if let Err(e) = result {
+        eprintln!("chirp: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn live_input(chippy: &mut Chip8) {
+    #[cfg(feature = "gamepad")]
+    chippy.keypad.set_input((Keyboard, gamepad::Gamepad::new()));
+    #[cfg(not(feature = "gamepad"))]
+    chippy.keypad.set_input(Keyboard);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn run(options: cli::Options) {
+    let mut chippy = Chip8::init(options.hz);
+    chippy.quirks = options.quirks;
+    chippy.timing = options.timing;
+    chippy.seed = options.seed.unwrap_or_else(clock_seed);
+    chippy.load_rom(&options.rom).unwrap(); // checked by `Options::parse`
+    chippy.trace = options.trace;
+    live_input(&mut chippy);
+
+    let mut movies = if options.record.is_some() {
+        prevent_quit(); // save the recording first
+        Movies::Recording(Movie::record(&mut chippy, &options.rom).unwrap())
+    } else if let Some(movie) = &options.play {
+        movie.play(&mut chippy, &options.rom).unwrap(); // checked by `Options::parse`
+        Movies::play(movie)
+    } else {
+        Movies::Off
+    };
+
+    // display choices on the command line become the saved ones
+    let saved = Theme::load();
+    let mut theme = match options.palette {
+        Some(palette) => Theme {
+            style: saved.style,
+            ..palette
+        },
+        None => saved,
+    };
+    theme.foreground = options.fg.unwrap_or(theme.foreground);
+    theme.background = options.bg.unwrap_or(theme.background);
+    theme.style = options.pixels.unwrap_or(theme.style);
+    if theme != saved {
+        save_theme(&theme);
+    }
+    let mut renderer = Renderer::new();
+    renderer.set_theme(&theme);
+    let mut slots = slots::QuickSlots::new();
+    let mut rewind = rewind_buffer();
+    let mut speaker = sound::Speaker::new(Beeper {
+        pitch: options.pitch,
+        ..Beeper::new()
+    });
+    let mut debugger = Debugger::new();
+    if options.paused {
+        debugger.pause();
+    }
+    let mut scheduler = Scheduler::new();
+    let mut speed = options.speed;
 
     loop {
-        let fps = get_fps();
+        // comma and period halve and double the speed
+        if is_key_pressed(KeyCode::Comma) || is_key_pressed(KeyCode::Period) {
+            let factor = if is_key_pressed(KeyCode::Comma) {
+                0.5
+            } else {
+                2.0
+            };
+            speed = (speed * factor).clamp(Scheduler::MIN_SPEED, Scheduler::MAX_SPEED);
+            slots.status = Some(format!("speed x{}", speed));
+        }
+        scheduler.set_speed(frame_speed(speed));
+        let frames = scheduler.frames(get_frame_time() as f64);
 
-        chippy.sync_cycle(fps);
-        chippy.display.draw(false);
+        // F2 and F3 cycle through the palettes and pixel styles
+        if is_key_pressed(THEME_KEY) || is_key_pressed(PIXEL_STYLE_KEY) {
+            slots.status = Some(if is_key_pressed(THEME_KEY) {
+                format!("palette {}", theme.next_palette())
+            } else {
+                theme.style = theme.style.next();
+                format!("{} pixels", theme.style.name())
+            });
+            renderer.set_theme(&theme);
+            save_theme(&theme);
+        }
+
+        if !movies.active() {
+            slots.update(&mut chippy);
+        }
+        debugview::update(&mut debugger, &mut chippy);
+        if is_key_down(REWIND_KEY) && !movies.active() {
+            rewind.rewind(&mut chippy);
+        } else {
+            for _ in 0..frames {
+                if debugger.paused {
+                    break;
+                }
+                // only whole frames count, a breakpoint can stop one early
+                match debugger.run_frame(&mut chippy) {
+                    Ok(true) => {
+                        rewind.record(&chippy);
+                        if let Some(status) = movies.frame_done(&chippy) {
+                            live_input(&mut chippy);
+                            slots.status = Some(status);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("chirp: {}", e),
+                }
+            }
+        }
+        if is_quit_requested() {
+            if let (Some(path), Some(movie)) = (&options.record, movies.stop()) {
+                if let Err(e) = std::fs::write(path, movie.to_bytes()) {
+                    eprintln!("chirp: {}: {}", path, e);
+                }
+            }
+            break;
+        }
+        if let Some(Err(e)) = chippy.trace.as_mut().map(|t| t.flush()) {
+            eprintln!("chirp: trace: {}", e);
+            chippy.trace = None;
+        }
+        speaker.update(&chippy).await;
+        renderer.draw(&chippy.display, options.bloom);
+        debugview::draw(&debugger, &chippy);
+        if let Some(e) = chippy.error {
+            draw_text(&e.to_string(), 8.0, 32.0, 32.0, RED);
+        }
+        if let Some(status) = &slots.status {
+            draw_text(status, 8.0, screen_height() - 12.0, 32.0, WHITE);
+        }
 
-        // draw_text(&format!("fps: {:?}", fps), 2.0, 20.0, 30.0, GREEN);
+        // draw_text(&format!("fps: {:?}", get_fps()), 2.0, 20.0, 30.0, GREEN);
         next_frame().await;
     }
 }
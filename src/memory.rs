@@ -1,25 +1,74 @@
-use std::ops::{Index, IndexMut};
+use std::ops::{Index, IndexMut, RangeInclusive};
 
 pub struct Memory {
-    mem: [u8; 4096],
+    mem: Box<[u8]>,                   // 64 KiB, XO-CHIP can address all of it
+    watch: Vec<RangeInclusive<u16>>,  // write watchpoints, see `debugger`
+    pub watch_hit: Option<(u16, u8)>, // first watched write since the last `take_watch_hit`
 
     #[cfg(target_arch = "wasm32")]
-    pub heatmap: [u8; 4096], // for visualization
+    pub heatmap: Box<[u8]>, // for visualization
 }
 
 impl Memory {
+    pub const SIZE: usize = 0x10000;
+
     pub fn new() -> Self {
         Self {
-            mem: [0; 4096],
+            mem: vec![0; Self::SIZE].into_boxed_slice(),
+            watch: Vec::new(),
+            watch_hit: None,
             #[cfg(target_arch = "wasm32")]
-            heatmap: [0; 4096],
+            heatmap: vec![0; Self::SIZE].into_boxed_slice(),
+        }
+    }
+
+    // raw contents, without touching the heatmap
+    pub fn bytes(&self) -> &[u8] {
+        &self.mem
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    // zero everything, watchpoints are kept
+    pub fn clear(&mut self) {
+        self.mem.fill(0);
+        self.watch_hit = None;
+        #[cfg(target_arch = "wasm32")]
+        self.heatmap.fill(0);
+    }
+
+    pub fn watch(&mut self, range: RangeInclusive<u16>) {
+        if !self.watch.contains(&range) {
+            self.watch.push(range);
+        }
+    }
+
+    pub fn unwatch(&mut self, range: &RangeInclusive<u16>) -> bool {
+        let len = self.watch.len();
+        self.watch.retain(|r| r != range);
+        self.watch.len() != len
+    }
+
+    pub fn watches(&self) -> &[RangeInclusive<u16>] {
+        &self.watch
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<(u16, u8)> {
+        self.watch_hit.take()
+    }
+
+    fn check_watch(&mut self, i: u16, v: u8) {
+        if self.watch_hit.is_none() && self.watch.iter().any(|r| r.contains(&i)) {
+            self.watch_hit = Some((i, v));
         }
     }
 
     #[cfg(target_arch = "wasm32")]
     pub fn tick(&mut self) {
-        for (i, m) in self.heatmap.into_iter().enumerate() {
-            self.heatmap[i] = m.saturating_sub(1)
+        for m in self.heatmap.iter_mut() {
+            *m = m.saturating_sub(1)
         }
     }
 
@@ -32,6 +81,7 @@ impl Memory {
 
     #[cfg(target_arch = "wasm32")]
     pub fn set(&mut self, i: u16, v: u8) {
+        self.check_watch(i, v);
         let i = i as usize;
         self.heatmap[i] = 255;
         self.mem[i] = v;
@@ -45,11 +95,18 @@ impl Memory {
 
     #[cfg(not(target_arch = "wasm32"))]
     pub fn set(&mut self, i: u16, v: u8) {
+        self.check_watch(i, v);
         let i = i as usize;
         self.mem[i] = v;
     }
 }
 
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index<&'_ u16> for Memory {
     type Output = u8;
 
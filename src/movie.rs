@@ -0,0 +1,223 @@
+// Input movies: the keypad state of every frame since a ROM was loaded.
+//
+// Together with the ROM, the seed, speed, quirks and timing in the header a movie
+// replays a session exactly, one `Chip8::run_frame` per recorded frame.
+//
+// Layout (all integers little endian):
+//   "CH8M" magic, u8 version
+//   header:  rom hash u64 (FNV-1a), seed u64, hz i32, quirks u8 (bitfield),
+//            timing u8
+//   frames:  count u32, then a u16 key bitmask per frame, bit n is key n
+use std::fmt;
+
+use crate::{
+    chip8::Chip8,
+    error::RomError,
+    input::Scripted,
+    quirks::Quirks,
+    savestate::{quirks_from_bits, quirks_to_bits, timing_from_u8},
+    timing::Timing,
+};
+
+const MAGIC: &[u8; 4] = b"CH8M";
+pub const VERSION: u8 = 2;
+const HEADER_SIZE: usize = 4 + 1 + 8 + 8 + 4 + 1 + 1 + 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Corrupt,
+    WrongRom, // the ROM's hash doesn't match the one recorded
+    Rom(RomError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a chirp movie"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            Self::Truncated => write!(f, "movie is truncated"),
+            Self::Corrupt => write!(f, "movie is corrupt"),
+            Self::WrongRom => write!(f, "movie was recorded with a different ROM"),
+            Self::Rom(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub hz: i32,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub frames: Vec<u16>, // key bitmask for each frame
+}
+
+impl Movie {
+    // restart `chippy` with `rom` and begin a movie of it
+    pub fn record(chippy: &mut Chip8, rom: &[u8]) -> Result<Self, MovieError> {
+        chippy.load_rom(rom).map_err(MovieError::Rom)?;
+        Ok(Self {
+            rom_hash: rom_hash(rom),
+            seed: chippy.seed,
+            hz: chippy.hz,
+            quirks: chippy.quirks,
+            timing: chippy.timing,
+            frames: Vec::new(),
+        })
+    }
+
+    // call after each recorded frame
+    pub fn record_frame(&mut self, chippy: &Chip8) {
+        let input = chippy.keypad.input();
+        let keys = (0..16).fold(0, |keys, k| keys | (input.is_key_down(k) as u16) << k);
+        self.frames.push(keys);
+    }
+
+    // restart `chippy` with `rom` and the recorded settings, and feed it the
+    // movie's keys in place of its input backend
+    pub fn play(&self, chippy: &mut Chip8, rom: &[u8]) -> Result<(), MovieError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(MovieError::WrongRom);
+        }
+        Chip8::check_rom(rom).map_err(MovieError::Rom)?;
+        chippy.seed = self.seed;
+        chippy.set_hz(self.hz);
+        chippy.quirks = self.quirks;
+        chippy.timing = self.timing;
+        chippy.load_rom(rom).map_err(MovieError::Rom)?;
+        chippy
+            .keypad
+            .set_input(Scripted::from_frames(self.frames.clone()));
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + 2 * self.frames.len());
+        out.extend(MAGIC);
+        out.push(VERSION);
+        out.extend(self.rom_hash.to_le_bytes());
+        out.extend(self.seed.to_le_bytes());
+        out.extend(self.hz.to_le_bytes());
+        out.push(quirks_to_bits(&self.quirks));
+        out.push(self.timing as u8);
+        out.extend((self.frames.len() as u32).to_le_bytes());
+        for keys in &self.frames {
+            out.extend(keys.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < 5 || &data[..4] != MAGIC {
+            return Err(if data.len() < 4 {
+                MovieError::Truncated
+            } else {
+                MovieError::BadMagic
+            });
+        }
+        if data[4] != VERSION {
+            return Err(MovieError::UnsupportedVersion(data[4]));
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(MovieError::Truncated);
+        }
+
+        let u64_at = |i: usize| u64::from_le_bytes(data[i..i + 8].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        let hz = u32_at(21) as i32;
+        if !(Chip8::MIN_HZ..=Chip8::MAX_HZ).contains(&hz) {
+            return Err(MovieError::Corrupt);
+        }
+        let timing = timing_from_u8(data[26]).ok_or(MovieError::Corrupt)?;
+        let count = u32_at(27) as usize;
+        let frames = &data[HEADER_SIZE..];
+        if frames.len() < 2 * count {
+            return Err(MovieError::Truncated);
+        }
+        Ok(Self {
+            rom_hash: u64_at(5),
+            seed: u64_at(13),
+            hz,
+            quirks: quirks_from_bits(data[25]),
+            timing,
+            frames: frames
+                .chunks_exact(2)
+                .take(count)
+                .map(|keys| u16::from_le_bytes([keys[0], keys[1]]))
+                .collect(),
+        })
+    }
+}
+
+// FNV-1a, to tell ROMs apart without storing them
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roms;
+
+    fn record(frames: &Scripted, count: usize) -> (Movie, Vec<u8>) {
+        let mut chippy = Chip8::init(700);
+        chippy.reseed(99);
+        chippy.quirks = Quirks::from_name("vip").unwrap();
+        let mut movie = Movie::record(&mut chippy, roms::Brix).unwrap();
+        chippy.keypad.set_input(frames.clone());
+        for _ in 0..count {
+            chippy.run_frame().unwrap();
+            movie.record_frame(&chippy);
+        }
+        (movie, chippy.save_state())
+    }
+
+    #[test]
+    fn play_back() {
+        let keys = Scripted::new().wait(10).hold(&[4], 20).hold(&[6], 20);
+        let (movie, state) = record(&keys, 60);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.frames.len(), 60);
+
+        let mut chippy = Chip8::init(500);
+        movie.play(&mut chippy, roms::Brix).unwrap();
+        for _ in 0..movie.frames.len() {
+            chippy.run_frame().unwrap();
+        }
+        assert_eq!(chippy.save_state(), state);
+        assert_eq!(
+            movie.play(&mut chippy, roms::Pong),
+            Err(MovieError::WrongRom)
+        );
+    }
+
+    #[test]
+    fn bad_movies() {
+        let (movie, _) = record(&Scripted::new(), 3);
+        let data = movie.to_bytes();
+        assert_eq!(
+            Movie::from_bytes(&data[..data.len() - 1]),
+            Err(MovieError::Truncated)
+        );
+        let mut bad = data.clone();
+        bad[4] = VERSION + 1;
+        assert_eq!(
+            Movie::from_bytes(&bad),
+            Err(MovieError::UnsupportedVersion(VERSION + 1))
+        );
+        // hz is at 21..25
+        for hz in [0, 30, Chip8::MAX_HZ + 1] {
+            let mut bad = data.clone();
+            bad[21..25].copy_from_slice(&hz.to_le_bytes());
+            assert_eq!(Movie::from_bytes(&bad), Err(MovieError::Corrupt));
+        }
+    }
+}
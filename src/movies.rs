@@ -0,0 +1,49 @@
+use chirp::{chip8::Chip8, movie::Movie};
+
+// Movie recording and playback for the frontends. Rewinding or loading a
+// state is disabled while a movie is active, either would desync it.
+pub enum Movies {
+    Off,
+    Recording(Movie),
+    Playing { frame: usize, len: usize },
+}
+
+impl Movies {
+    pub fn active(&self) -> bool {
+        !matches!(self, Self::Off)
+    }
+
+    pub fn play(movie: &Movie) -> Self {
+        Self::Playing {
+            frame: 0,
+            len: movie.frames.len(),
+        }
+    }
+
+    // call after each emulated frame, returns a message when playback ends
+    pub fn frame_done(&mut self, chippy: &Chip8) -> Option<String> {
+        match self {
+            Self::Off => None,
+            Self::Recording(movie) => {
+                movie.record_frame(chippy);
+                None
+            }
+            Self::Playing { frame, len } => {
+                *frame += 1;
+                if frame < len {
+                    return None;
+                }
+                *self = Self::Off;
+                Some("movie finished".to_owned())
+            }
+        }
+    }
+
+    // end recording or playback, returning the recorded movie
+    pub fn stop(&mut self) -> Option<Movie> {
+        match std::mem::replace(self, Self::Off) {
+            Self::Recording(movie) => Some(movie),
+            _ => None,
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use macroquad::rand;
-
-use crate::{chip8::Chip8, display::Display};
+use crate::{
+    chip8::Chip8,
+    error::{EmulatorError, ErrorPolicy},
+    instruction::{decode, Instruction},
+    timing::Timing,
+};
 
 pub enum PC {
     Next,
@@ -10,306 +13,1053 @@ pub enum PC {
 
 #[allow(non_snake_case)]
 impl Chip8 {
-    // clear screen
-    pub fn exec_opcode(&mut self, opcode: u16) {
-        let i = ((opcode & 0xF000) >> 12) as u8;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as u8;
-        let nn = (opcode & 0x00FF) as u8;
-        let nnn = opcode & 0x0FFF;
-
-        match match (i, x, y, n) {
-            (0x00, 0x00, 0x00, 0x00) => PC::Jump(self.pc),
-
-            (0x00, 0x00, 0x0E, 0x00) => self._00E0(), // clear screen
-            (0x00, 0x00, 0x0E, 0x0E) => self._00EE(), // return from subroutine
-
-            (0x01, _, _, _) => self._1NNN(nnn),     // jump
-            (0x02, _, _, _) => self._2NNN(nnn),     // call subroutine at nnn
-            (0x03, _, _, _) => self._3XNN(x, nn),   // skip if Vx == nn
-            (0x04, _, _, _) => self._4XNN(x, nn),   // skip if Vx != nn
-            (0x05, _, _, 0x00) => self._5XY0(x, y), // skip if Vx == Vy
-            (0x06, _, _, _) => self._6XNN(x, nn),   // set register Vx to nn
-            (0x07, _, _, _) => self._7XNN(x, nn),   // add value to register Vx
-
-            (0x08, _, _, 0x00) => self._8XY0(x, y), // set Vx to Vy
-            (0x08, _, _, 0x01) => self._8XY1(x, y), // set Vx to Vx | Vy
-            (0x08, _, _, 0x02) => self._8XY2(x, y), // set Vx to Vx & Vy
-            (0x08, _, _, 0x03) => self._8XY3(x, y), // set Vx to Vx ^ Vy
-            (0x08, _, _, 0x04) => self._8XY4(x, y), // Add Vy to Vx
-            (0x08, _, _, 0x05) => self._8XY5(x, y), // set Vx to Vx - Vy
-            (0x08, _, _, 0x06) => self._8XY6(x, y), // shift right Vx by 1
-            (0x08, _, _, 0x07) => self._8XY7(x, y), // set Vx to Vy - Vx
-            (0x08, _, _, 0x0E) => self._8XYE(x, y), // shift left Vx by 1
-
-            (0x09, _, _, 0x00) => self._9XY0(x, y), // skip if Vx != Vy
-
-            (0x0A, _, _, _) => self._ANNN(nnn), // set index to register I
-            (0x0B, _, _, _) => self._BNNN(nnn), // jump to v0 + nnn
-            (0x0C, _, _, _) => self._CXNN(x, nn), // set Vx to a random number & nn
-            (0x0D, _, _, _) => self._DXYN(x, y, n), // display/draw
-
-            (0x0E, _, 0x09, 0x0E) => self._EX9E(x), // skip if key down
-            (0x0E, _, 0x0A, 0x01) => self._EXA1(x), // skip if key not down
-
-            (0x0F, _, 0x00, 0x07) => self._FX07(x), // set Vx to value of delay timer
-            (0x0F, _, 0x01, 0x05) => self._FX15(x), // set delay timer to value of Vx
-            (0x0F, _, 0x01, 0x08) => self._FX18(x), // set sound timer to value of Vx
-            (0x0F, _, 0x01, 0x0E) => self._FX1E(x), // add Vx to I
-            (0x0F, _, 0x00, 0x0A) => self._FX0A(x), // get key
-            (0x0F, _, 0x02, 0x09) => self._FX29(x), // set I to be the font in Vx
-            (0x0F, _, 0x03, 0x03) => self._FX33(x), // get each number place and store in memory
-            (0x0F, _, 0x05, 0x05) => self._FX55(x), // store registers to memory
-            (0x0F, _, 0x06, 0x05) => self._FX65(x), // load memory to registers
-
-            _ => {
-                println!("Unknown Opcode: {:#06X}", opcode);
-                PC::Next
-            }
-        } {
-            PC::Next => self.pc += 0x02,
+    // execute one opcode and advance the pc
+    pub fn exec_opcode(&mut self, opcode: u16) -> Result<(), EmulatorError> {
+        match match decode(opcode) {
+            Ok(instruction) => self.execute(instruction),
+            Err(_) => self.illegal(opcode),
+        }? {
+            PC::Next => self.pc = self.pc.wrapping_add(0x02),
             PC::Jump(nnn) => self.pc = nnn,
             PC::Skip(skip) => {
                 if skip {
-                    self.pc += 0x04
+                    // F000 NNNN is twice as long, skip both words of it
+                    if self.fetch(self.pc.wrapping_add(0x02)) == 0xF000 {
+                        self.pc = self.pc.wrapping_add(0x06)
+                    } else {
+                        self.pc = self.pc.wrapping_add(0x04)
+                    }
                 } else {
-                    self.pc += 0x02
+                    self.pc = self.pc.wrapping_add(0x02)
                 }
             }
         }
+        Ok(())
+    }
+
+    // run a decoded instruction, returning how the pc moves
+    fn execute(&mut self, instruction: Instruction) -> Result<PC, EmulatorError> {
+        use Instruction::*;
+
+        match instruction {
+            Idle => Ok(PC::Jump(self.pc)),
+            ScrollDown { n } => self._00CN(n),
+            ScrollUp { n } => self._00DN(n),
+            Clear => self._00E0(),
+            Return => self._00EE(),
+            ScrollRight => self._00FB(),
+            ScrollLeft => self._00FC(),
+            Exit => self._00FD(),
+            LoRes => self._00FE(),
+            HiRes => self._00FF(),
+            Jump { nnn } => self._1NNN(nnn),
+            Call { nnn } => self._2NNN(nnn),
+            SkipEqImm { x, nn } => self._3XNN(x as usize, nn),
+            SkipNeImm { x, nn } => self._4XNN(x as usize, nn),
+            SkipEq { x, y } => self._5XY0(x as usize, y as usize),
+            SaveRange { x, y } => self._5XY2(x as usize, y as usize),
+            LoadRange { x, y } => self._5XY3(x as usize, y as usize),
+            SetImm { x, nn } => self._6XNN(x as usize, nn),
+            AddImm { x, nn } => self._7XNN(x as usize, nn),
+            Set { x, y } => self._8XY0(x as usize, y as usize),
+            Or { x, y } => self._8XY1(x as usize, y as usize),
+            And { x, y } => self._8XY2(x as usize, y as usize),
+            Xor { x, y } => self._8XY3(x as usize, y as usize),
+            Add { x, y } => self._8XY4(x as usize, y as usize),
+            Sub { x, y } => self._8XY5(x as usize, y as usize),
+            ShiftRight { x, y } => self._8XY6(x as usize, y as usize),
+            SubReverse { x, y } => self._8XY7(x as usize, y as usize),
+            ShiftLeft { x, y } => self._8XYE(x as usize, y as usize),
+            SkipNe { x, y } => self._9XY0(x as usize, y as usize),
+            SetI { nnn } => self._ANNN(nnn),
+            JumpOffset { nnn } => self._BNNN(nnn),
+            Random { x, nn } => self._CXNN(x as usize, nn),
+            DrawBig { x, y } => self._DXY0(x as usize, y as usize),
+            Draw { x, y, n } => self._DXYN(x as usize, y as usize, n),
+            SkipKey { x } => self._EX9E(x as usize),
+            SkipNotKey { x } => self._EXA1(x as usize),
+            SetILong { .. } => self._F000(),
+            Plane { n } => self._FN01(n as usize),
+            Audio => self._F002(),
+            GetDelay { x } => self._FX07(x as usize),
+            SetDelay { x } => self._FX15(x as usize),
+            SetSound { x } => self._FX18(x as usize),
+            AddI { x } => self._FX1E(x as usize),
+            WaitKey { x } => self._FX0A(x as usize),
+            Font { x } => self._FX29(x as usize),
+            BigFont { x } => self._FX30(x as usize),
+            Bcd { x } => self._FX33(x as usize),
+            Pitch { x } => self._FX3A(x as usize),
+            Store { x } => self._FX55(x as usize),
+            Load { x } => self._FX65(x as usize),
+            SaveFlags { x } => self._FX75(x as usize),
+            LoadFlags { x } => self._FX85(x as usize),
+        }
+    }
+
+    // Unknown opcode
+    fn illegal(&mut self, opcode: u16) -> Result<PC, EmulatorError> {
+        match self.on_error {
+            ErrorPolicy::Halt => Err(EmulatorError::IllegalOpcode {
+                pc: self.pc,
+                opcode,
+            }),
+            ErrorPolicy::Wrap | ErrorPolicy::Ignore => Ok(PC::Next),
+        }
     }
 
     // Clear the display.
-    fn _00E0(&mut self) -> PC {
+    fn _00E0(&mut self) -> Result<PC, EmulatorError> {
         self.display.clear();
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Jump to location nnn
-    fn _1NNN(&mut self, nnn: u16) -> PC {
-        PC::Jump(nnn)
+    fn _1NNN(&mut self, nnn: u16) -> Result<PC, EmulatorError> {
+        Ok(PC::Jump(nnn))
     }
 
     // Set Vx = nn
-    fn _6XNN(&mut self, x: usize, nn: u8) -> PC {
+    fn _6XNN(&mut self, x: usize, nn: u8) -> Result<PC, EmulatorError> {
         self.v[x] = nn;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Add nn to Vx
-    fn _7XNN(&mut self, x: usize, nn: u8) -> PC {
+    fn _7XNN(&mut self, x: usize, nn: u8) -> Result<PC, EmulatorError> {
         self.v[x] = self.v[x].wrapping_add(nn);
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set I = nnn
-    fn _ANNN(&mut self, nnn: u16) -> PC {
+    fn _ANNN(&mut self, nnn: u16) -> Result<PC, EmulatorError> {
         self.i = nnn;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Display draw
-    fn _DXYN(&mut self, x: usize, y: usize, n: u8) -> PC {
-        let x = self.v[x] % Display::WIDTH;
-        let mut y = self.v[y] % Display::HEIGHT;
+    fn _DXYN(&mut self, x: usize, y: usize, n: u8) -> Result<PC, EmulatorError> {
+        if !self.wait_for_vblank() {
+            return Ok(PC::Jump(self.pc));
+        }
+        self.draw_sprite(x, y, n, false)?;
+        Ok(PC::Next)
+    }
+
+    // Display draw a 16x16 sprite
+    fn _DXY0(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        if !self.wait_for_vblank() {
+            return Ok(PC::Jump(self.pc));
+        }
+        self.draw_sprite(x, y, 16, true)?;
+        Ok(PC::Next)
+    }
+
+    // with the display wait quirk (and always with VIP timing) only one draw
+    // happens per frame, returns false if the draw has to be retried next frame
+    fn wait_for_vblank(&mut self) -> bool {
+        if !self.quirks.display_wait && self.timing != Timing::Cycles {
+            return true;
+        }
+        std::mem::replace(&mut self.vblank, false)
+    }
+
+    // xor a sprite of `rows` rows at I onto each selected plane, 16 pixels per
+    // row if wide. The data for each plane follows the previous plane's.
+    fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        rows: u8,
+        wide: bool,
+    ) -> Result<(), EmulatorError> {
+        let (width, height) = (self.display.width(), self.display.height());
+        let clipping = self.quirks.clipping;
+        let x = self.v[x] % width;
+        let y = self.v[y] % height;
+        let (bytes, cols) = if wide { (2, 16) } else { (1, 8) };
+        let size = rows as u32 * bytes;
         self.v[0x0F] = 0x00;
 
-        for b in 0..n as u16 {
-            let sprite_data = self.memory.get(self.i + b);
-            let mut x = x;
-            for i in (0..8).rev() {
-                if ((sprite_data >> i) & 1) != 0 {
-                    let prev = self.display.get(x, y);
-                    self.display.set(x, y, !prev);
-                    if prev {
-                        self.v[0x0F] = 0x01;
+        let planes: Vec<usize> = self.display.selected_planes().collect();
+        for (p, &plane) in planes.iter().enumerate() {
+            let base = self.i as u32 + (p as u32) * size;
+            for row in 0..rows {
+                let addr = base + (row as u32) * bytes;
+                let sprite_data = if wide {
+                    ((self.read(addr)? as u16) << 8) | (self.read(addr + 1)? as u16)
+                } else {
+                    self.read(addr)? as u16
+                };
+                let y = y + row;
+                if clipping && y >= height {
+                    break;
+                }
+                for col in 0..cols {
+                    if ((sprite_data >> (cols - 1 - col)) & 1) != 0 {
+                        let x = x + col;
+                        if clipping && x >= width {
+                            break;
+                        }
+                        let prev = self.display.get_plane(plane, x, y);
+                        self.display.set_plane(plane, x, y, !prev);
+                        if prev {
+                            self.v[0x0F] = 0x01;
+                        }
                     }
                 }
-                x += 1;
             }
-            y += 1;
         }
+        Ok(())
+    }
+
+    // Scroll down n rows
+    fn _00CN(&mut self, n: u8) -> Result<PC, EmulatorError> {
+        self.display.scroll_down(n);
+        Ok(PC::Next)
+    }
+
+    // Scroll up n rows
+    fn _00DN(&mut self, n: u8) -> Result<PC, EmulatorError> {
+        self.display.scroll_up(n);
+        Ok(PC::Next)
+    }
+
+    // Scroll right 4 pixels
+    fn _00FB(&mut self) -> Result<PC, EmulatorError> {
+        self.display.scroll_right(4);
+        Ok(PC::Next)
+    }
+
+    // Scroll left 4 pixels
+    fn _00FC(&mut self) -> Result<PC, EmulatorError> {
+        self.display.scroll_left(4);
+        Ok(PC::Next)
+    }
+
+    // Exit the interpreter
+    fn _00FD(&mut self) -> Result<PC, EmulatorError> {
+        self.halted = true;
+        Ok(PC::Jump(self.pc))
+    }
+
+    // Switch to 64x32 low resolution
+    fn _00FE(&mut self) -> Result<PC, EmulatorError> {
+        self.display.set_hires(false);
+        Ok(PC::Next)
+    }
 
-        PC::Next
+    // Switch to 128x64 high resolution
+    fn _00FF(&mut self) -> Result<PC, EmulatorError> {
+        self.display.set_hires(true);
+        Ok(PC::Next)
     }
 
     // Return from subroutine
-    fn _00EE(&mut self) -> PC {
-        PC::Jump(self.stack.pop())
+    fn _00EE(&mut self) -> Result<PC, EmulatorError> {
+        match self.pop()? {
+            Some(addr) => Ok(PC::Jump(addr)),
+            None => Ok(PC::Next),
+        }
     }
 
     // Call subroutine at nnn
-    fn _2NNN(&mut self, nnn: u16) -> PC {
+    fn _2NNN(&mut self, nnn: u16) -> Result<PC, EmulatorError> {
         // push the incremented pc so that, the next opcode is called instead of looping
-        self.stack.push(self.pc + 0x02);
-        PC::Jump(nnn)
+        self.push(self.pc.wrapping_add(0x02))?;
+        Ok(PC::Jump(nnn))
     }
 
     // Skip if Vx == nn
-    fn _3XNN(&mut self, x: usize, nn: u8) -> PC {
-        PC::Skip(self.v[x] == nn)
+    fn _3XNN(&mut self, x: usize, nn: u8) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(self.v[x] == nn))
     }
 
     // Skip if Vx != nn
-    fn _4XNN(&mut self, x: usize, nn: u8) -> PC {
-        PC::Skip(self.v[x] != nn)
+    fn _4XNN(&mut self, x: usize, nn: u8) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(self.v[x] != nn))
     }
 
     // Skip if Vx == Vy
-    fn _5XY0(&mut self, x: usize, y: usize) -> PC {
-        PC::Skip(self.v[x] == self.v[y])
+    fn _5XY0(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(self.v[x] == self.v[y]))
     }
 
     // Skip if Vx != Vy
-    fn _9XY0(&mut self, x: usize, y: usize) -> PC {
-        PC::Skip(self.v[x] != self.v[y])
+    fn _9XY0(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(self.v[x] != self.v[y]))
+    }
+
+    // Store Vx..Vy to memory at I, in either order
+    fn _5XY2(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        for (offset, r) in Self::register_range(x, y).enumerate() {
+            self.write(self.i as u32 + offset as u32, self.v[r])?;
+        }
+        Ok(PC::Next)
+    }
+
+    // Load Vx..Vy from memory at I, in either order
+    fn _5XY3(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        for (offset, r) in Self::register_range(x, y).enumerate() {
+            self.v[r] = self.read(self.i as u32 + offset as u32)?;
+        }
+        Ok(PC::Next)
+    }
+
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
     }
 
     // Set Vx to Vy
-    fn _8XY0(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY0(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         self.v[x] = self.v[y];
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set Vx to Vx | Vy
-    fn _8XY1(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY1(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         self.v[x] |= self.v[y];
-        PC::Next
+        if self.quirks.vf_reset {
+            self.v[0x0F] = 0x00;
+        }
+        Ok(PC::Next)
     }
 
     // Set Vx to Vx & Vy
-    fn _8XY2(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY2(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         self.v[x] &= self.v[y];
-        PC::Next
+        if self.quirks.vf_reset {
+            self.v[0x0F] = 0x00;
+        }
+        Ok(PC::Next)
     }
 
     // Set Vx to Vx ^ Vy
-    fn _8XY3(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY3(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         self.v[x] ^= self.v[y];
-        PC::Next
+        if self.quirks.vf_reset {
+            self.v[0x0F] = 0x00;
+        }
+        Ok(PC::Next)
     }
 
     // Add Vy to Vx
-    fn _8XY4(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY4(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         let (sum, overflow) = self.v[x].overflowing_add(self.v[y]);
         self.v[x] = sum;
         self.v[0x0F] = overflow as u8;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set Vx to Vx - Vy
-    fn _8XY5(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY5(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         let (diff, overflow) = self.v[x].overflowing_sub(self.v[y]);
         self.v[x] = diff;
         self.v[0x0F] = !overflow as u8;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set Vx to Vy - Vx
-    fn _8XY7(&mut self, x: usize, y: usize) -> PC {
+    fn _8XY7(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
         let (diff, overflow) = self.v[y].overflowing_sub(self.v[x]);
         self.v[x] = diff;
         self.v[0x0F] = !overflow as u8;
-        PC::Next
+        Ok(PC::Next)
     }
 
-    // Shift right Vx by 1
-    fn _8XY6(&mut self, x: usize, _y: usize) -> PC {
-        self.v[0x0F] = self.v[x] & 1;
-        self.v[x] >>= 1;
-        PC::Next
+    // Shift right Vx (or Vy, see `Quirks::shift`) by 1
+    fn _8XY6(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        let src = if self.quirks.shift {
+            self.v[x]
+        } else {
+            self.v[y]
+        };
+        self.v[x] = src >> 1;
+        self.v[0x0F] = src & 1;
+        Ok(PC::Next)
     }
 
-    // Shift left Vx by 1
-    fn _8XYE(&mut self, x: usize, _y: usize) -> PC {
-        self.v[0x0F] = (self.v[x] & 255) >> 7;
-        self.v[x] <<= 1;
-        PC::Next
+    // Shift left Vx (or Vy, see `Quirks::shift`) by 1
+    fn _8XYE(&mut self, x: usize, y: usize) -> Result<PC, EmulatorError> {
+        let src = if self.quirks.shift {
+            self.v[x]
+        } else {
+            self.v[y]
+        };
+        self.v[x] = src << 1;
+        self.v[0x0F] = src >> 7;
+        Ok(PC::Next)
     }
 
-    // Jump to V0 + nnn
-    fn _BNNN(&mut self, nnn: u16) -> PC {
-        PC::Jump(self.v[0] as u16 + nnn)
+    // Jump to V0 + nnn (or Vx + nnn, see `Quirks::jump`)
+    fn _BNNN(&mut self, nnn: u16) -> Result<PC, EmulatorError> {
+        let offset = if self.quirks.jump {
+            self.v[(nnn >> 8) as usize]
+        } else {
+            self.v[0]
+        };
+        Ok(PC::Jump(offset as u16 + nnn))
     }
 
     // Set Vx to a random number & nn
-    fn _CXNN(&mut self, x: usize, nn: u8) -> PC {
-        self.v[x] = rand::gen_range(0, 255) & nn;
-        PC::Next
+    fn _CXNN(&mut self, x: usize, nn: u8) -> Result<PC, EmulatorError> {
+        self.v[x] = self.rng.next_u8() & nn;
+        Ok(PC::Next)
     }
 
     // Skip if key (Vx) down
-    fn _EX9E(&mut self, x: usize) -> PC {
-        PC::Skip(self.keypad.is_key_down(self.v[x]))
+    fn _EX9E(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(self.keypad.is_key_down(self.v[x])))
     }
 
     // Skip if key (Vx) not down
-    fn _EXA1(&mut self, x: usize) -> PC {
-        PC::Skip(!self.keypad.is_key_down(self.v[x]))
+    fn _EXA1(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        Ok(PC::Skip(!self.keypad.is_key_down(self.v[x])))
+    }
+
+    // Set I to the 16 bit word following this opcode
+    fn _F000(&mut self) -> Result<PC, EmulatorError> {
+        self.i = self.fetch(self.pc.wrapping_add(0x02));
+        Ok(PC::Jump(self.pc.wrapping_add(0x04)))
+    }
+
+    // Select the drawing planes, n is a bitmask
+    fn _FN01(&mut self, n: usize) -> Result<PC, EmulatorError> {
+        self.display.planes = n as u8;
+        Ok(PC::Next)
+    }
+
+    // Load 16 bytes at I into the audio pattern buffer
+    fn _F002(&mut self) -> Result<PC, EmulatorError> {
+        for b in 0..16 {
+            self.audio_pattern[b] = self.read(self.i as u32 + b as u32)?;
+        }
+        Ok(PC::Next)
     }
 
     // Set Vx to value of delay timer
-    fn _FX07(&mut self, x: usize) -> PC {
+    fn _FX07(&mut self, x: usize) -> Result<PC, EmulatorError> {
         self.v[x] = self.delay_timer;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set delay timer to value of Vx
-    fn _FX15(&mut self, x: usize) -> PC {
+    fn _FX15(&mut self, x: usize) -> Result<PC, EmulatorError> {
         self.delay_timer = self.v[x];
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set sound timer to value of Vx
-    fn _FX18(&mut self, x: usize) -> PC {
+    fn _FX18(&mut self, x: usize) -> Result<PC, EmulatorError> {
         self.sound_timer = self.v[x];
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Add Vx to I
-    fn _FX1E(&mut self, x: usize) -> PC {
+    fn _FX1E(&mut self, x: usize) -> Result<PC, EmulatorError> {
         let (sum, overflow) = self.i.overflowing_add(self.v[x] as u16);
         self.i = sum;
         self.v[0x0F] = overflow as u8;
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Get key
-    fn _FX0A(&mut self, x: usize) -> PC {
+    fn _FX0A(&mut self, x: usize) -> Result<PC, EmulatorError> {
         self.keypad.wait_for_key(x);
-        PC::Next
+        Ok(PC::Next)
     }
 
     // Set I to be the font in Vx
-    fn _FX29(&mut self, x: usize) -> PC {
+    fn _FX29(&mut self, x: usize) -> Result<PC, EmulatorError> {
         self.i = (self.v[x] as u16) * 5; // *5 to get the last nibble
-        PC::Next
+        Ok(PC::Next)
+    }
+
+    // Set I to be the big font in Vx
+    fn _FX30(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        self.i = Self::BIG_FONT_OFFSET + (self.v[x] as u16) * 10;
+        Ok(PC::Next)
+    }
+
+    // Set the audio playback pitch to Vx
+    fn _FX3A(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        self.pitch = self.v[x];
+        Ok(PC::Next)
     }
 
     // get each number place and store in memory
-    fn _FX33(&mut self, x: usize) -> PC {
+    fn _FX33(&mut self, x: usize) -> Result<PC, EmulatorError> {
         let vx = self.v[x];
-        self.memory.set(self.i, vx / 100);
-        self.memory.set(self.i + 1, (vx / 10) % 10);
-        self.memory.set(self.i + 2, (vx % 100) % 10);
+        let i = self.i as u32;
+        self.write(i, vx / 100)?;
+        self.write(i + 1, (vx / 10) % 10)?;
+        self.write(i + 2, (vx % 100) % 10)?;
 
-        PC::Next
+        Ok(PC::Next)
     }
 
     // store registers to memory
-    fn _FX55(&mut self, x: usize) -> PC {
+    fn _FX55(&mut self, x: usize) -> Result<PC, EmulatorError> {
         for i in 0..x + 1 {
-            self.memory.set(self.i + (i as u16), self.v[i]);
+            self.write(self.i as u32 + i as u32, self.v[i])?;
+        }
+        if !self.quirks.load_store {
+            self.i = self.i.wrapping_add(x as u16 + 1);
         }
-        PC::Next
+        Ok(PC::Next)
     }
 
     // load memory to registers
-    fn _FX65(&mut self, x: usize) -> PC {
+    fn _FX65(&mut self, x: usize) -> Result<PC, EmulatorError> {
         for i in 0..x + 1 {
-            self.v[i] = self.memory.get(self.i + (i as u16));
+            self.v[i] = self.read(self.i as u32 + i as u32)?;
+        }
+        if !self.quirks.load_store {
+            self.i = self.i.wrapping_add(x as u16 + 1);
         }
-        PC::Next
+        Ok(PC::Next)
+    }
+
+    // store V0 to Vx in the rpl user flags
+    fn _FX75(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+        Ok(PC::Next)
+    }
+
+    // load V0 to Vx from the rpl user flags
+    fn _FX85(&mut self, x: usize) -> Result<PC, EmulatorError> {
+        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
+        Ok(PC::Next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    fn machine() -> Chip8 {
+        let mut chippy = Chip8::init(1000);
+        chippy.load_font(0x00);
+        chippy
+    }
+
+    fn exec(chippy: &mut Chip8, opcode: u16) {
+        chippy.exec_opcode(opcode).unwrap();
+    }
+
+    fn lit(chippy: &Chip8) -> usize {
+        chippy.display.buffer.iter().filter(|&&p| p != 0).count()
+    }
+
+    #[test]
+    fn clear_00e0() {
+        let mut chippy = machine();
+        chippy.display.set_plane(0, 3, 4, true);
+        exec(&mut chippy, 0x00E0);
+        assert_eq!(lit(&chippy), 0);
+        assert_eq!(chippy.pc, 0x202);
+    }
+
+    #[test]
+    fn scroll_00cn_00dn() {
+        let mut chippy = machine();
+        chippy.display.set_plane(0, 5, 1, true);
+        exec(&mut chippy, 0x00C3);
+        assert!(chippy.display.get(5, 4));
+        exec(&mut chippy, 0x00D2);
+        assert!(chippy.display.get(5, 2));
+        assert_eq!(lit(&chippy), 1);
+    }
+
+    #[test]
+    fn scroll_00fb_00fc() {
+        let mut chippy = machine();
+        chippy.display.set_plane(0, 5, 1, true);
+        exec(&mut chippy, 0x00FB);
+        assert!(chippy.display.get(9, 1));
+        exec(&mut chippy, 0x00FC);
+        exec(&mut chippy, 0x00FC);
+        assert!(chippy.display.get(1, 1));
+        assert_eq!(lit(&chippy), 1);
+    }
+
+    #[test]
+    fn exit_00fd() {
+        let mut chippy = machine();
+        exec(&mut chippy, 0x00FD);
+        assert!(chippy.halted);
+        assert_eq!(chippy.pc, 0x200);
+    }
+
+    #[test]
+    fn resolution_00fe_00ff() {
+        let mut chippy = machine();
+        chippy.display.set_plane(0, 0, 0, true);
+        exec(&mut chippy, 0x00FF);
+        assert!(chippy.display.hires);
+        assert_eq!(lit(&chippy), 0);
+        exec(&mut chippy, 0x00FE);
+        assert!(!chippy.display.hires);
+    }
+
+    #[test]
+    fn jump_1nnn() {
+        let mut chippy = machine();
+        exec(&mut chippy, 0x1ABC);
+        assert_eq!(chippy.pc, 0xABC);
+    }
+
+    #[test]
+    fn call_2nnn_return_00ee() {
+        let mut chippy = machine();
+        exec(&mut chippy, 0x2400);
+        assert_eq!(chippy.pc, 0x400);
+        assert_eq!(chippy.stack.depth(), 1);
+        exec(&mut chippy, 0x00EE);
+        assert_eq!(chippy.pc, 0x202);
+        assert_eq!(chippy.stack.depth(), 0);
+    }
+
+    #[test]
+    fn stack_errors() {
+        let mut chippy = machine();
+        assert_eq!(
+            chippy.exec_opcode(0x00EE),
+            Err(EmulatorError::StackUnderflow { pc: 0x200 })
+        );
+        for _ in 0..16 {
+            exec(&mut chippy, 0x2200);
+        }
+        assert_eq!(
+            chippy.exec_opcode(0x2200),
+            Err(EmulatorError::StackOverflow { pc: 0x200 })
+        );
+    }
+
+    #[test]
+    fn skip_3xnn_4xnn() {
+        let mut chippy = machine();
+        chippy.v[3] = 0x42;
+        exec(&mut chippy, 0x3342);
+        assert_eq!(chippy.pc, 0x204);
+        exec(&mut chippy, 0x3343);
+        assert_eq!(chippy.pc, 0x206);
+        exec(&mut chippy, 0x4342);
+        assert_eq!(chippy.pc, 0x208);
+        exec(&mut chippy, 0x4343);
+        assert_eq!(chippy.pc, 0x20C);
+    }
+
+    #[test]
+    fn skip_5xy0_9xy0() {
+        let mut chippy = machine();
+        chippy.v[1] = 7;
+        chippy.v[2] = 7;
+        exec(&mut chippy, 0x5120);
+        assert_eq!(chippy.pc, 0x204);
+        exec(&mut chippy, 0x9120);
+        assert_eq!(chippy.pc, 0x206);
+        chippy.v[2] = 8;
+        exec(&mut chippy, 0x9120);
+        assert_eq!(chippy.pc, 0x20A);
+    }
+
+    #[test]
+    fn skip_over_f000() {
+        let mut chippy = machine();
+        chippy.load(0x202, vec![0xF0, 0x00, 0x12, 0x34]);
+        exec(&mut chippy, 0x3000);
+        assert_eq!(chippy.pc, 0x206);
+    }
+
+    #[test]
+    fn range_5xy2_5xy3() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.v[1..=3].copy_from_slice(&[1, 2, 3]);
+        exec(&mut chippy, 0x5132);
+        assert_eq!(chippy.memory.get(0x300), 1);
+        assert_eq!(chippy.memory.get(0x302), 3);
+        // loading from Vy down to Vx reverses the order, I is unchanged
+        exec(&mut chippy, 0x5643);
+        assert_eq!(&chippy.v[4..=6], &[3, 2, 1]);
+        assert_eq!(chippy.i, 0x300);
+    }
+
+    #[test]
+    fn set_6xnn_add_7xnn() {
+        let mut chippy = machine();
+        exec(&mut chippy, 0x65F0);
+        assert_eq!(chippy.v[5], 0xF0);
+        exec(&mut chippy, 0x7520);
+        assert_eq!(chippy.v[5], 0x10);
+        // no carry flag
+        assert_eq!(chippy.v[0xF], 0);
+    }
+
+    #[test]
+    fn logic_8xy0_to_8xy3() {
+        let mut chippy = machine();
+        chippy.v[1] = 0b1100;
+        chippy.v[2] = 0b1010;
+        chippy.v[0xF] = 9;
+        exec(&mut chippy, 0x8011);
+        exec(&mut chippy, 0x8021);
+        assert_eq!(chippy.v[0], 0b1110);
+        exec(&mut chippy, 0x8012);
+        assert_eq!(chippy.v[0], 0b1100);
+        exec(&mut chippy, 0x8023);
+        assert_eq!(chippy.v[0], 0b0110);
+        assert_eq!(chippy.v[0xF], 9);
+
+        chippy.quirks.vf_reset = true;
+        exec(&mut chippy, 0x8013);
+        assert_eq!(chippy.v[0xF], 0);
+
+        // a plain copy leaves VF alone, even with vf_reset
+        chippy.v[0xF] = 9;
+        exec(&mut chippy, 0x8020);
+        assert_eq!(chippy.v[0], chippy.v[2]);
+        assert_eq!(chippy.v[0xF], 9);
+    }
+
+    #[test]
+    fn add_8xy4() {
+        let mut chippy = machine();
+        chippy.v[0] = 0xF0;
+        chippy.v[1] = 0x20;
+        exec(&mut chippy, 0x8014);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0x10, 1));
+        exec(&mut chippy, 0x8014);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0x30, 0));
+        // the flag wins when VF is the destination
+        chippy.v[0xF] = 0xFF;
+        exec(&mut chippy, 0x8F14);
+        assert_eq!(chippy.v[0xF], 1);
+    }
+
+    #[test]
+    fn sub_8xy5() {
+        let mut chippy = machine();
+        chippy.v[0] = 5;
+        chippy.v[1] = 3;
+        exec(&mut chippy, 0x8015);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (2, 1));
+        exec(&mut chippy, 0x8015);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0xFF, 0));
+        // no borrow when equal
+        chippy.v[0] = 3;
+        exec(&mut chippy, 0x8015);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0, 1));
+        chippy.v[0xF] = 1;
+        exec(&mut chippy, 0x8F15);
+        assert_eq!(chippy.v[0xF], 0);
+    }
+
+    #[test]
+    fn subn_8xy7() {
+        let mut chippy = machine();
+        chippy.v[0] = 3;
+        chippy.v[1] = 5;
+        exec(&mut chippy, 0x8017);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (2, 1));
+        chippy.v[0] = 6;
+        exec(&mut chippy, 0x8017);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0xFF, 0));
+    }
+
+    #[test]
+    fn shift_8xy6() {
+        let mut chippy = machine();
+        chippy.v[0] = 0b101;
+        chippy.v[1] = 0b110;
+        exec(&mut chippy, 0x8016);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0b10, 1));
+
+        chippy.quirks.shift = false;
+        exec(&mut chippy, 0x8016);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0b11, 0));
+    }
+
+    #[test]
+    fn shift_8xye() {
+        let mut chippy = machine();
+        chippy.v[0] = 0x81;
+        chippy.v[1] = 0x40;
+        exec(&mut chippy, 0x801E);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0x02, 1));
+
+        chippy.quirks.shift = false;
+        exec(&mut chippy, 0x801E);
+        assert_eq!((chippy.v[0], chippy.v[0xF]), (0x80, 0));
+    }
+
+    #[test]
+    fn set_i_annn() {
+        let mut chippy = machine();
+        exec(&mut chippy, 0xA123);
+        assert_eq!(chippy.i, 0x123);
+    }
+
+    #[test]
+    fn jump_offset_bnnn() {
+        let mut chippy = machine();
+        chippy.v[0] = 0x10;
+        chippy.v[3] = 0x20;
+        exec(&mut chippy, 0xB300);
+        assert_eq!(chippy.pc, 0x310);
+
+        chippy.quirks.jump = true;
+        exec(&mut chippy, 0xB300);
+        assert_eq!(chippy.pc, 0x320);
+    }
+
+    #[test]
+    fn random_cxnn() {
+        let mut chippy = machine();
+        chippy.reseed(7);
+        let values: Vec<u8> = (0..32)
+            .map(|_| {
+                exec(&mut chippy, 0xC00F);
+                chippy.v[0]
+            })
+            .collect();
+        assert!(values.iter().all(|&v| v <= 0x0F));
+
+        // the same seed gives the same numbers
+        chippy.reseed(7);
+        for &v in &values {
+            exec(&mut chippy, 0xC00F);
+            assert_eq!(chippy.v[0], v);
+        }
+    }
+
+    #[test]
+    fn draw_dxyn() {
+        let mut chippy = machine();
+        // the font's 0 at (1, 2)
+        chippy.v[0] = 1;
+        chippy.v[1] = 2;
+        exec(&mut chippy, 0xD015);
+        assert_eq!(lit(&chippy), 14);
+        assert!(chippy.display.get(1, 2));
+        assert!(!chippy.display.get(2, 3));
+        assert_eq!(chippy.v[0xF], 0);
+
+        // drawing it again erases it and reports the collision
+        exec(&mut chippy, 0xD015);
+        assert_eq!(lit(&chippy), 0);
+        assert_eq!(chippy.v[0xF], 1);
+    }
+
+    #[test]
+    fn draw_dxyn_edges() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.load(0x300, vec![0xFF]);
+        chippy.v[0] = 60;
+        exec(&mut chippy, 0xD011);
+        assert!(chippy.display.get(0, 0));
+
+        chippy.display.clear();
+        chippy.quirks.clipping = true;
+        exec(&mut chippy, 0xD011);
+        assert_eq!(lit(&chippy), 4);
+        assert!(!chippy.display.get(0, 0));
+    }
+
+    #[test]
+    fn draw_dxyn_display_wait() {
+        let mut chippy = machine();
+        chippy.quirks.display_wait = true;
+        exec(&mut chippy, 0xD015);
+        assert_eq!(chippy.pc, 0x200);
+        assert_eq!(lit(&chippy), 0);
+
+        chippy.vblank = true;
+        exec(&mut chippy, 0xD015);
+        assert_eq!(chippy.pc, 0x202);
+        assert!(!chippy.vblank);
+    }
+
+    #[test]
+    fn draw_dxy0() {
+        let mut chippy = machine();
+        chippy.display.set_hires(true);
+        chippy.i = 0x300;
+        chippy.load(0x300, vec![0xFF; 32]);
+        exec(&mut chippy, 0xD000);
+        assert_eq!(lit(&chippy), 256);
+        assert!(chippy.display.get(15, 15));
+    }
+
+    #[test]
+    fn draw_planes() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.load(0x300, vec![0x80, 0x80]);
+        exec(&mut chippy, 0xF301);
+        exec(&mut chippy, 0xD001);
+        assert_eq!(chippy.display.pixel(0, 0), 0b11);
+        // clearing only touches the selected planes
+        exec(&mut chippy, 0xF101);
+        exec(&mut chippy, 0x00E0);
+        assert_eq!(chippy.display.pixel(0, 0), 0b10);
+        assert_eq!(chippy.display.planes, 0b01);
+    }
+
+    #[test]
+    fn keys_ex9e_exa1() {
+        let mut chippy = machine();
+        chippy.keypad.set_input(1u16 << 0xA);
+        chippy.v[0] = 0xA;
+        exec(&mut chippy, 0xE09E);
+        assert_eq!(chippy.pc, 0x204);
+        exec(&mut chippy, 0xE0A1);
+        assert_eq!(chippy.pc, 0x206);
+        chippy.v[0] = 0xB;
+        exec(&mut chippy, 0xE0A1);
+        assert_eq!(chippy.pc, 0x20A);
+    }
+
+    #[test]
+    fn keys_above_f_ex9e() {
+        let mut chippy = machine();
+        chippy.keypad.set_input(1u16);
+        chippy.v[0] = 0x20;
+        exec(&mut chippy, 0xE09E);
+        assert_eq!(chippy.pc, 0x204);
+        chippy.v[0] = 0x21;
+        exec(&mut chippy, 0xE09E);
+        assert_eq!(chippy.pc, 0x206);
+    }
+
+    #[test]
+    fn long_i_f000() {
+        let mut chippy = machine();
+        chippy.load(0x202, vec![0xBE, 0xEF]);
+        exec(&mut chippy, 0xF000);
+        assert_eq!(chippy.i, 0xBEEF);
+        assert_eq!(chippy.pc, 0x204);
+    }
+
+    #[test]
+    fn audio_f002_fx3a() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.load(0x300, (0..16).collect());
+        exec(&mut chippy, 0xF002);
+        assert_eq!(chippy.audio_pattern[15], 15);
+        chippy.v[2] = 100;
+        exec(&mut chippy, 0xF23A);
+        assert_eq!(chippy.pitch, 100);
+    }
+
+    #[test]
+    fn timers_fx07_fx15_fx18() {
+        let mut chippy = machine();
+        chippy.v[1] = 30;
+        exec(&mut chippy, 0xF115);
+        exec(&mut chippy, 0xF118);
+        assert_eq!((chippy.delay_timer, chippy.sound_timer), (30, 30));
+        chippy.delay_timer = 12;
+        exec(&mut chippy, 0xF207);
+        assert_eq!(chippy.v[2], 12);
+    }
+
+    #[test]
+    fn add_i_fx1e() {
+        let mut chippy = machine();
+        chippy.i = 0xFFFE;
+        chippy.v[0] = 1;
+        exec(&mut chippy, 0xF01E);
+        assert_eq!((chippy.i, chippy.v[0xF]), (0xFFFF, 0));
+        exec(&mut chippy, 0xF01E);
+        assert_eq!((chippy.i, chippy.v[0xF]), (0x0000, 1));
+    }
+
+    #[test]
+    fn wait_key_fx0a() {
+        let mut chippy = machine();
+        chippy.load(0x200, vec![0xF3, 0x0A]);
+        chippy.cycle().unwrap();
+        assert!(chippy.keypad.waiting);
+        chippy.cycle().unwrap();
+        assert!(chippy.keypad.waiting);
+
+        chippy.keypad.set_input(1u16 << 0x7);
+        chippy.cycle().unwrap();
+        assert!(!chippy.keypad.waiting);
+        assert_eq!(chippy.v[3], 0x7);
+    }
+
+    #[test]
+    fn font_fx29_fx30() {
+        let mut chippy = machine();
+        chippy.v[0] = 0xA;
+        exec(&mut chippy, 0xF029);
+        assert_eq!(chippy.i, 50);
+        exec(&mut chippy, 0xF030);
+        assert_eq!(chippy.i, Chip8::BIG_FONT_OFFSET + 100);
+    }
+
+    #[test]
+    fn bcd_fx33() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.v[0] = 234;
+        exec(&mut chippy, 0xF033);
+        let digits: Vec<u8> = (0..3).map(|d| chippy.memory.get(0x300 + d)).collect();
+        assert_eq!(digits, [2, 3, 4]);
+    }
+
+    #[test]
+    fn store_load_fx55_fx65() {
+        let mut chippy = machine();
+        chippy.i = 0x300;
+        chippy.v[..3].copy_from_slice(&[1, 2, 3]);
+        exec(&mut chippy, 0xF255);
+        assert_eq!(chippy.i, 0x300);
+        chippy.v = [0; 16];
+        exec(&mut chippy, 0xF165);
+        assert_eq!(&chippy.v[..3], &[1, 2, 0]);
+
+        chippy.quirks = Quirks::VIP;
+        exec(&mut chippy, 0xF265);
+        assert_eq!(&chippy.v[..3], &[1, 2, 3]);
+        assert_eq!(chippy.i, 0x303);
+        exec(&mut chippy, 0xF055);
+        assert_eq!(chippy.i, 0x304);
+    }
+
+    #[test]
+    fn flags_fx75_fx85() {
+        let mut chippy = machine();
+        chippy.v[..3].copy_from_slice(&[1, 2, 3]);
+        exec(&mut chippy, 0xF275);
+        chippy.v = [0; 16];
+        exec(&mut chippy, 0xF185);
+        assert_eq!(&chippy.v[..3], &[1, 2, 0]);
+        assert_eq!(&chippy.rpl[..3], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn illegal_opcode() {
+        let mut chippy = machine();
+        assert_eq!(
+            chippy.exec_opcode(0xFFFF),
+            Err(EmulatorError::IllegalOpcode {
+                pc: 0x200,
+                opcode: 0xFFFF
+            })
+        );
+        chippy.on_error = ErrorPolicy::Ignore;
+        exec(&mut chippy, 0xFFFF);
+        assert_eq!(chippy.pc, 0x202);
     }
 }
@@ -1,5 +1,4 @@
 use macroquad::prelude::*;
-use std::usize;
 
 // never ended up using this
 
@@ -20,7 +19,7 @@ impl<const SIZE: usize> GfxPipeline<SIZE> {
         }
     }
 
-    pub fn pipe(&self, draw: &dyn Fn() -> ()) {
+    pub fn pipe(&self, draw: &dyn Fn()) {
         if SIZE == 0 {
             clear_background(TRANSPARENT);
             draw();
@@ -101,7 +100,7 @@ impl GfxShader {
         gl_use_default_material();
     }
 
-    pub fn apply(&self, draw: &dyn Fn() -> ()) {
+    pub fn apply(&self, draw: &dyn Fn()) {
         if self.impose {
             self.impose_apply(draw);
             return;
@@ -111,7 +110,7 @@ impl GfxShader {
         self.close();
     }
 
-    pub fn impose_apply(&self, draw: &dyn Fn() -> ()) {
+    pub fn impose_apply(&self, draw: &dyn Fn()) {
         self.open();
         draw();
         self.close();
@@ -0,0 +1,71 @@
+// Behaviour of opcodes that differ between CHIP-8 interpreters.
+//
+// Different ROMs were written against different interpreters, so pick the
+// profile a ROM expects with `Chip8::quirks`. `Quirks::default()` is chirp's
+// original behaviour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift: bool,        // 8XY6/8XYE shift Vx in place, otherwise Vx = Vy >> 1
+    pub load_store: bool,   // FX55/FX65 leave I unchanged, otherwise I += x + 1
+    pub jump: bool,         // BNNN jumps to Vx + nnn (BXNN), otherwise V0 + nnn
+    pub vf_reset: bool,     // 8XY1/8XY2/8XY3 reset VF to 0
+    pub clipping: bool,     // sprites are clipped at the screen edges, otherwise wrap
+    pub display_wait: bool, // DXYN waits for the next frame (one draw per frame)
+}
+
+impl Quirks {
+    // the original COSMAC VIP interpreter
+    pub const VIP: Self = Self {
+        shift: false,
+        load_store: false,
+        jump: false,
+        vf_reset: true,
+        clipping: true,
+        display_wait: true,
+    };
+
+    // SUPER-CHIP 1.1 on the HP48
+    pub const SCHIP: Self = Self {
+        shift: true,
+        load_store: true,
+        jump: true,
+        vf_reset: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    // XO-CHIP as implemented by Octo
+    pub const XOCHIP: Self = Self {
+        shift: false,
+        load_store: false,
+        jump: false,
+        vf_reset: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    pub const PROFILES: [&'static str; 4] = ["chirp", "vip", "schip", "xochip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chirp" => Some(Self::default()),
+            "vip" => Some(Self::VIP),
+            "schip" => Some(Self::SCHIP),
+            "xochip" => Some(Self::XOCHIP),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift: true,
+            load_store: true,
+            jump: false,
+            vf_reset: false,
+            clipping: false,
+            display_wait: false,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use chirp::display::Display;
+use macroquad::prelude::*;
+
+use crate::{
+    postprocessing::GfxPipeline,
+    shaders::Bloom, // ChromaticAberration, GaussianBlur
+    theme::{PixelStyle, Theme},
+};
+
+// colour of each combination of XO-CHIP bit-planes, index 0 is the background
+const PALETTE: [Color; 16] = [
+    Color::new(0.0, 0.0, 0.0, 0.0),
+    Color::new(1.0, 0.4, 0.78823525, 1.0), // plane 1, the classic pink
+    Color::new(0.4, 0.8, 1.0, 1.0),
+    Color::new(1.0, 1.0, 1.0, 1.0),
+    Color::new(1.0, 0.8, 0.2, 1.0),
+    Color::new(1.0, 0.6, 0.4, 1.0),
+    Color::new(0.4, 1.0, 0.6, 1.0),
+    Color::new(0.8, 0.8, 0.8, 1.0),
+    Color::new(0.6, 0.4, 1.0, 1.0),
+    Color::new(1.0, 0.3, 0.3, 1.0),
+    Color::new(0.3, 0.6, 1.0, 1.0),
+    Color::new(0.9, 0.9, 0.5, 1.0),
+    Color::new(0.5, 0.3, 0.2, 1.0),
+    Color::new(0.3, 0.5, 0.3, 1.0),
+    Color::new(0.3, 0.3, 0.5, 1.0),
+    Color::new(0.6, 0.6, 0.6, 1.0),
+];
+
+// draws a chip8 `Display` to the macroquad window
+pub struct Renderer {
+    pub palette: [Color; 16],        // plane colours, see `PALETTE`
+    pub background: Color,           // cleared to before drawing
+    pub style: PixelStyle,           // shape of each pixel
+    width: f32,                      // screen width
+    height: f32,                     // screen height
+    post_processing: GfxPipeline<1>, // never ended up using this
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        let width = screen_width();
+        let height = screen_height();
+        Self {
+            palette: PALETTE,
+            background: BLACK,
+            style: PixelStyle::default(),
+            width,
+            height,
+            post_processing: GfxPipeline::new(width, height, &[(*Bloom, true)]),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.palette[1] = theme.foreground;
+        self.background = theme.background;
+        self.style = theme.style;
+    }
+
+    pub fn update_screen_size(&mut self) {
+        let width = screen_width();
+        let height = screen_height();
+
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            self.post_processing.update_dimensions(width, height);
+        }
+    }
+
+    pub fn draw(&mut self, display: &Display, post: bool) {
+        self.update_screen_size();
+        clear_background(self.background);
+
+        // pixel size follows the display mode (64x32 or 128x64)
+        let width_ratio = self.width / display.width() as f32;
+        let height_ratio = self.height / display.height() as f32;
+
+        let (palette, style, background) = (&self.palette, self.style, self.background);
+        let (screen_width, screen_height) = (self.width, self.height);
+        let draw = &|| {
+            for y in 0..display.height() {
+                for x in 0..display.width() {
+                    let pixel = display.pixel(x, y);
+                    if pixel != 0 {
+                        draw_pixel(
+                            width_ratio * x as f32,
+                            height_ratio * y as f32,
+                            width_ratio,
+                            height_ratio,
+                            palette[pixel as usize],
+                            style,
+                        );
+                    }
+                }
+            }
+            if style == PixelStyle::Grid {
+                // faint lines, a fifth of the way from the background to plane 1
+                let (bg, fg) = (background, palette[1]);
+                let blend = |b: f32, f: f32| b + (f - b) * 0.2;
+                let colour =
+                    Color::new(blend(bg.r, fg.r), blend(bg.g, fg.g), blend(bg.b, fg.b), 1.0);
+                for x in 1..display.width() {
+                    let x = width_ratio * x as f32;
+                    draw_line(x, 0.0, x, screen_height, 1.0, colour);
+                }
+                for y in 1..display.height() {
+                    let y = height_ratio * y as f32;
+                    draw_line(0.0, y, screen_width, y, 1.0, colour);
+                }
+            }
+        };
+
+        if post {
+            self.post_processing.pipe(&draw);
+        } else {
+            draw()
+        }
+    }
+}
+
+// one lit pixel filling the w x h cell at x, y
+fn draw_pixel(x: f32, y: f32, w: f32, h: f32, colour: Color, style: PixelStyle) {
+    match style {
+        PixelStyle::Square | PixelStyle::Grid => draw_rectangle(x, y, w, h, colour),
+        PixelStyle::Gaps => {
+            let gap = (w.min(h) * 0.15).max(1.0);
+            draw_rectangle(x + gap / 2.0, y + gap / 2.0, w - gap, h - gap, colour);
+        }
+        PixelStyle::Rounded => {
+            // a cross of two rectangles, with a circle in each corner
+            let r = w.min(h) * 0.3;
+            draw_rectangle(x + r, y, w - 2.0 * r, h, colour);
+            draw_rectangle(x, y + r, w, h - 2.0 * r, colour);
+            for (cx, cy) in [
+                (x + r, y + r),
+                (x + w - r, y + r),
+                (x + r, y + h - r),
+                (x + w - r, y + h - r),
+            ] {
+                draw_circle(cx, cy, r, colour);
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Rewind buffer for stepping gameplay backwards.
+//
+// Every `interval` frames a save state is taken. Only the newest state is kept
+// whole; each older one is stored as a delta against the state after it, so
+// stepping back costs one delta and dropping the oldest is free.
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+
+pub struct Rewind {
+    capacity: usize,           // max snapshots kept
+    interval: usize,           // frames between snapshots
+    frame: usize,              // frames since the last snapshot
+    newest: Option<Vec<u8>>,   // full save state of the newest snapshot
+    deltas: VecDeque<Vec<u8>>, // older snapshots, oldest first
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            frame: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.frame = 0;
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // call once per emulated frame
+    pub fn record(&mut self, chippy: &Chip8) {
+        self.frame += 1;
+        if self.frame < self.interval && self.newest.is_some() {
+            return;
+        }
+        self.frame = 0;
+
+        let state = chippy.save_state();
+        if let Some(prev) = self.newest.replace(state) {
+            let delta = diff(self.newest.as_ref().unwrap(), &prev);
+            self.deltas.push_back(delta);
+        }
+        if self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // restore the newest snapshot and drop it, so each call steps further back.
+    // The oldest snapshot is kept. Returns false if there is nothing to restore.
+    pub fn rewind(&mut self, chippy: &mut Chip8) -> bool {
+        let Some(newest) = self.newest.take() else {
+            return false;
+        };
+        // states come from save_state so they always load
+        chippy.load_state(&newest).ok();
+
+        self.newest = Some(match self.deltas.pop_back() {
+            Some(delta) => patch(&newest, &delta),
+            None => newest,
+        });
+        self.frame = 0;
+        true
+    }
+}
+
+// Encode the bytes of `to` that differ from `from` (same length) as runs of
+// [unchanged count][changed count][changed bytes], counts as LEB128.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < to.len() {
+        let start = i;
+        while i < to.len() && from[i] == to[i] {
+            i += 1;
+        }
+        if i == to.len() {
+            break;
+        }
+        let skip = i - start;
+
+        let changed = i;
+        while i < to.len() && from[i] != to[i] {
+            i += 1;
+        }
+        write_len(&mut out, skip);
+        write_len(&mut out, i - changed);
+        out.extend_from_slice(&to[changed..i]);
+    }
+    out
+}
+
+fn patch(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut out = from.to_vec();
+    let (mut pos, mut d) = (0, 0);
+    while d < delta.len() {
+        pos += read_len(delta, &mut d);
+        let len = read_len(delta, &mut d);
+        out[pos..pos + len].copy_from_slice(&delta[d..d + len]);
+        pos += len;
+        d += len;
+    }
+    out
+}
+
+fn write_len(out: &mut Vec<u8>, mut n: usize) {
+    loop {
+        let b = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(b);
+            return;
+        }
+        out.push(b | 0x80);
+    }
+}
+
+fn read_len(data: &[u8], d: &mut usize) -> usize {
+    let (mut n, mut shift) = (0, 0);
+    loop {
+        let b = data[*d];
+        *d += 1;
+        n |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roms;
+
+    fn machine() -> Chip8 {
+        let mut chippy = Chip8::init(1000);
+        chippy.reseed(7);
+        chippy.load_rom(roms::Brix).unwrap();
+        chippy
+    }
+
+    #[test]
+    fn diff_patch() {
+        let from: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mut to = from.clone();
+        to[0] = 0xFF;
+        to[300..600].fill(0);
+        to[999] = 0;
+        let delta = diff(&from, &to);
+        assert_eq!(patch(&from, &delta), to);
+        assert!(diff(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn rewind_restores_states() {
+        let mut chippy = machine();
+        let mut rewind = Rewind::new(4, 1);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            chippy.run_frame().unwrap();
+            rewind.record(&chippy);
+            states.push(chippy.save_state());
+        }
+        // the ring has wrapped, only the last 4 are left
+        assert_eq!(rewind.len(), 4);
+
+        for expected in states[6..].iter().rev() {
+            assert!(rewind.rewind(&mut chippy));
+            assert_eq!(&chippy.save_state(), expected);
+        }
+        // the oldest is kept
+        assert!(rewind.rewind(&mut chippy));
+        assert_eq!(chippy.save_state(), states[6]);
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn record_every_interval() {
+        let mut chippy = machine();
+        let mut rewind = Rewind::new(8, 3);
+        let mut states = Vec::new();
+        for frame in 0..12 {
+            chippy.run_frame().unwrap();
+            rewind.record(&chippy);
+            if frame % 3 == 0 {
+                states.push(chippy.save_state());
+            }
+        }
+        assert_eq!(rewind.len(), states.len());
+        for expected in states.iter().rev() {
+            assert!(rewind.rewind(&mut chippy));
+            assert_eq!(&chippy.save_state(), expected);
+        }
+
+        rewind.clear();
+        assert!(rewind.is_empty());
+        assert!(!rewind.rewind(&mut chippy));
+    }
+}
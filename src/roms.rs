@@ -13,8 +13,20 @@ pub const Particles: ROM = include_bytes!("roms/particle.ch8");
 
 pub const Test: ROM = include_bytes!("roms/test_opcode.ch8");
 
-pub fn get_rom(name: &String) -> ROM {
-    match name.as_str() {
+// names accepted by `get_rom`
+pub const NAMES: [&str; 8] = [
+    "tetris",
+    "brix",
+    "pong",
+    "keypad",
+    "invaders",
+    "tictactoe",
+    "ibmlogo",
+    "particles",
+];
+
+pub fn find_rom(name: &str) -> Option<ROM> {
+    Some(match name {
         "tetris" => Tetris,
         "brix" => Brix,
         "pong" => Pong,
@@ -23,6 +35,11 @@ pub fn get_rom(name: &String) -> ROM {
         "tictactoe" => TicTacToe,
         "ibmlogo" => IBMLogo,
         "particles" => Particles,
-        _ => Test,
-    }
+        _ => return None,
+    })
+}
+
+// unknown names get the opcode test ROM
+pub fn get_rom(name: &str) -> ROM {
+    find_rom(name).unwrap_or(Test)
 }
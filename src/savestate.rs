@@ -0,0 +1,414 @@
+// Save states: a snapshot of the complete machine.
+//
+// Layout (all integers little endian):
+//   "CH8S" magic, u8 version
+//   cpu:     hz i32, tick i32, i u16, pc u16, delay u8, sound u8, v[16], rpl[16]
+//   rng:     seed u64, state u64
+//   flags:   halted u8, vblank u8, quirks u8 (bitfield), timing u8, on_error u8, error
+//   audio:   pattern[16], pitch u8
+//   keypad:  waiting u8, dest_register u8
+//   display: hires u8, planes u8, buffer[128 * 64]
+//   stack:   pointer u8, slots [u16; 16]
+//   memory:  [u8; 0x10000]
+//
+// The input backend and tracer are not part of the state, they belong to the
+// frontend.
+use std::fmt;
+
+use crate::{
+    chip8::Chip8,
+    display::Display,
+    error::{EmulatorError, ErrorPolicy},
+    memory::Memory,
+    quirks::Quirks,
+    rng::Rng,
+    stack::Stack,
+    timing::Timing,
+};
+
+const MAGIC: &[u8; 4] = b"CH8S";
+pub const VERSION: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Corrupt,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a chirp save state"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl Chip8 {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::with_capacity(Memory::SIZE + Display::LENGTH + 128));
+        w.bytes(MAGIC);
+        w.u8(VERSION);
+
+        w.bytes(&self.hz.to_le_bytes());
+        w.bytes(&self.tick.to_le_bytes());
+        w.u16(self.i);
+        w.u16(self.pc);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.bytes(&self.v);
+        w.bytes(&self.rpl);
+        w.bytes(&self.seed.to_le_bytes());
+        w.bytes(&self.rng.state.to_le_bytes());
+
+        w.u8(self.halted as u8);
+        w.u8(self.vblank as u8);
+        w.u8(quirks_to_bits(&self.quirks));
+        w.u8(self.timing as u8);
+        w.u8(self.on_error as u8);
+        write_error(&mut w, self.error);
+
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        w.u8(self.keypad.waiting as u8);
+        w.u8(self.keypad.dest_register as u8);
+
+        w.u8(self.display.hires as u8);
+        w.u8(self.display.planes);
+        w.bytes(&self.display.buffer);
+
+        w.u8(self.stack.depth() as u8);
+        for &slot in self.stack.slots() {
+            w.u16(slot);
+        }
+
+        w.bytes(self.memory.bytes());
+        w.0
+    }
+
+    // restore a state made by `save_state`, the machine is untouched on error
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4)? != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        // decode everything before touching the machine
+        let hz = i32::from_le_bytes(r.array()?);
+        let tick = i32::from_le_bytes(r.array()?);
+        let i = r.u16()?;
+        let pc = r.u16()?;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let v: [u8; 16] = r.array()?;
+        let rpl: [u8; 16] = r.array()?;
+        let seed = u64::from_le_bytes(r.array()?);
+        let rng = Rng::new(u64::from_le_bytes(r.array()?));
+
+        let halted = r.bool()?;
+        let vblank = r.bool()?;
+        let quirks = quirks_from_bits(r.u8()?);
+        let timing = timing_from_u8(r.u8()?).ok_or(SaveStateError::Corrupt)?;
+        // a clock out of range would overflow on the next instruction
+        if !(Chip8::MIN_HZ..=Chip8::MAX_HZ).contains(&hz) || !(0..timing.rate(hz)).contains(&tick) {
+            return Err(SaveStateError::Corrupt);
+        }
+        let on_error = match r.u8()? {
+            0 => ErrorPolicy::Halt,
+            1 => ErrorPolicy::Wrap,
+            2 => ErrorPolicy::Ignore,
+            _ => return Err(SaveStateError::Corrupt),
+        };
+        let error = read_error(&mut r)?;
+
+        let audio_pattern: [u8; 16] = r.array()?;
+        let pitch = r.u8()?;
+
+        let waiting = r.bool()?;
+        let dest_register = r.u8()? as usize;
+        if dest_register > 0xF {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        let hires = r.bool()?;
+        let planes = r.u8()?;
+        let buffer = r.bytes(Display::LENGTH)?;
+        // pixels are palette indices, one bit per plane
+        let colours = 1 << Display::PLANES;
+        if planes >= colours || buffer.iter().any(|&p| p >= colours) {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        let pointer = r.u8()? as usize;
+        let mut slots = [0; Stack::DEPTH];
+        for slot in slots.iter_mut() {
+            *slot = r.u16()?;
+        }
+        if pointer > Stack::DEPTH {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        let memory = r.bytes(Memory::SIZE)?;
+
+        self.hz = hz;
+        self.tick = tick;
+        self.i = i;
+        self.pc = pc;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.v = v;
+        self.rpl = rpl;
+        self.seed = seed;
+        self.rng = rng;
+        self.halted = halted;
+        self.vblank = vblank;
+        self.quirks = quirks;
+        self.timing = timing;
+        self.on_error = on_error;
+        self.error = error;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.keypad.waiting = waiting;
+        self.keypad.dest_register = dest_register;
+        self.display.hires = hires;
+        self.display.planes = planes;
+        self.display.buffer.copy_from_slice(buffer);
+        self.stack.restore(slots, pointer);
+        self.memory.bytes_mut().copy_from_slice(memory);
+        // the next frame starts afresh
+        self.in_frame = false;
+        Ok(())
+    }
+}
+
+pub(crate) fn timing_from_u8(b: u8) -> Option<Timing> {
+    match b {
+        0 => Some(Timing::Instructions),
+        1 => Some(Timing::Cycles),
+        _ => None,
+    }
+}
+
+pub(crate) fn quirks_to_bits(q: &Quirks) -> u8 {
+    (q.shift as u8)
+        | (q.load_store as u8) << 1
+        | (q.jump as u8) << 2
+        | (q.vf_reset as u8) << 3
+        | (q.clipping as u8) << 4
+        | (q.display_wait as u8) << 5
+}
+
+pub(crate) fn quirks_from_bits(b: u8) -> Quirks {
+    Quirks {
+        shift: b & 1 != 0,
+        load_store: (b >> 1) & 1 != 0,
+        jump: (b >> 2) & 1 != 0,
+        vf_reset: (b >> 3) & 1 != 0,
+        clipping: (b >> 4) & 1 != 0,
+        display_wait: (b >> 5) & 1 != 0,
+    }
+}
+
+fn write_error(w: &mut Writer, error: Option<EmulatorError>) {
+    match error {
+        None => w.u8(0),
+        Some(EmulatorError::StackOverflow { pc }) => {
+            w.u8(1);
+            w.u16(pc);
+        }
+        Some(EmulatorError::StackUnderflow { pc }) => {
+            w.u8(2);
+            w.u16(pc);
+        }
+        Some(EmulatorError::OutOfBounds { pc, addr }) => {
+            w.u8(3);
+            w.u16(pc);
+            w.bytes(&addr.to_le_bytes());
+        }
+        Some(EmulatorError::IllegalOpcode { pc, opcode }) => {
+            w.u8(4);
+            w.u16(pc);
+            w.u16(opcode);
+        }
+    }
+}
+
+fn read_error(r: &mut Reader) -> Result<Option<EmulatorError>, SaveStateError> {
+    Ok(match r.u8()? {
+        0 => None,
+        1 => Some(EmulatorError::StackOverflow { pc: r.u16()? }),
+        2 => Some(EmulatorError::StackUnderflow { pc: r.u16()? }),
+        3 => Some(EmulatorError::OutOfBounds {
+            pc: r.u16()?,
+            addr: u32::from_le_bytes(r.array()?),
+        }),
+        4 => Some(EmulatorError::IllegalOpcode {
+            pc: r.u16()?,
+            opcode: r.u16()?,
+        }),
+        _ => return Err(SaveStateError::Corrupt),
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.0.extend_from_slice(b);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.pos + n;
+        let b = self
+            .data
+            .get(self.pos..end)
+            .ok_or(SaveStateError::Truncated)?;
+        self.pos = end;
+        Ok(b)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roms;
+
+    // brix a few frames in, so the state isn't all zeroes
+    fn machine() -> Chip8 {
+        let mut chippy = Chip8::init(1000);
+        chippy.reseed(42);
+        chippy.load_rom(roms::Brix).unwrap();
+        for _ in 0..30 {
+            chippy.run_frame().unwrap();
+        }
+        chippy
+    }
+
+    #[test]
+    fn round_trip() {
+        let chippy = machine();
+        let state = chippy.save_state();
+        let mut other = Chip8::init(500);
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+
+        // both machines carry on the same way
+        let mut chippy = chippy;
+        for _ in 0..30 {
+            chippy.run_frame().unwrap();
+            other.run_frame().unwrap();
+        }
+        assert_eq!(other.save_state(), chippy.save_state());
+    }
+
+    #[test]
+    fn truncated() {
+        let state = machine().save_state();
+        let mut chippy = Chip8::init(500);
+        let before = chippy.save_state();
+        for len in [0, 4, 5, 40, state.len() - 1] {
+            let err = chippy.load_state(&state[..len]).unwrap_err();
+            assert_eq!(err, SaveStateError::Truncated, "{} bytes", len);
+        }
+        assert_eq!(chippy.save_state(), before);
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut state = machine().save_state();
+        let mut chippy = Chip8::init(500);
+        state[4] = VERSION + 1;
+        assert_eq!(
+            chippy.load_state(&state),
+            Err(SaveStateError::UnsupportedVersion(VERSION + 1))
+        );
+        state[0] = b'X';
+        assert_eq!(chippy.load_state(&state), Err(SaveStateError::BadMagic));
+    }
+
+    #[test]
+    fn corrupt_clock() {
+        let state = machine().save_state();
+        let mut chippy = Chip8::init(500);
+        // hz is at 5..9 and tick at 9..13
+        for (pos, value) in [
+            (5, 0),
+            (5, -1),
+            (5, Chip8::MAX_HZ + 1),
+            (9, -1),
+            (9, 1000),
+            (9, i32::MAX),
+        ] {
+            let mut bad = state.clone();
+            bad[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+            assert_eq!(
+                chippy.load_state(&bad),
+                Err(SaveStateError::Corrupt),
+                "{} at {}",
+                value,
+                pos
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_display() {
+        let state = machine().save_state();
+        let mut chippy = Chip8::init(500);
+        // the buffer follows hires and planes, which follow the keypad
+        let buffer = state.len() - Memory::SIZE - 1 - 2 * Stack::DEPTH - Display::LENGTH;
+        for (pos, ok, bad) in [(buffer - 1, 0b11, 0x10), (buffer + 7, 0xF, 0x10)] {
+            let mut state = state.clone();
+            state[pos] = ok;
+            assert_eq!(chippy.load_state(&state), Ok(()));
+            state[pos] = bad;
+            assert_eq!(chippy.load_state(&state), Err(SaveStateError::Corrupt));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Fixed-timestep scheduling.
+//
+// Frontends render at whatever rate the display runs, but the machine always
+// runs in 60Hz frames (`Chip8::run_frame`). The scheduler turns real elapsed
+// time into a whole number of those frames, carrying the remainder over so
+// that on average exactly 60 run per second at normal speed.
+pub struct Scheduler {
+    pub speed: f64,        // 1.0 real time, above fast-forwards, below is slow motion
+    pub max_catch_up: u32, // frames owed beyond this (at 1x) are dropped
+    owed: f64,             // frames due but not yet run
+}
+
+impl Scheduler {
+    pub const FPS: f64 = 60.0;
+    pub const MIN_SPEED: f64 = 0.125;
+    pub const MAX_SPEED: f64 = 16.0;
+
+    pub fn new() -> Self {
+        Self {
+            speed: 1.0,
+            max_catch_up: 4,
+            owed: 0.0,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+    }
+
+    // how many frames to run for `elapsed` seconds of real time. After a
+    // stall (a dragged window, a breakpoint, a background tab) the backlog is
+    // capped instead of running hundreds of frames at once.
+    pub fn frames(&mut self, elapsed: f64) -> u32 {
+        if elapsed.is_finite() {
+            self.owed += elapsed.max(0.0) * self.speed * Self::FPS;
+        }
+        let limit = self.max_catch_up as f64 * self.speed.max(1.0);
+        self.owed = self.owed.min(limit);
+
+        let frames = self.owed.floor();
+        self.owed -= frames;
+        frames as u32
+    }
+
+    // forget time owed, e.g. after pausing
+    pub fn reset(&mut self) {
+        self.owed = 0.0;
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_the_remainder() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.frames(1.0 / 120.0), 0);
+        assert_eq!(scheduler.frames(1.0 / 120.0), 1);
+        assert_eq!(scheduler.frames(0.025), 1);
+        assert_eq!(scheduler.frames(0.025), 2);
+
+        // 64 fps for a second runs exactly 60 frames
+        scheduler.reset();
+        let total: u32 = (0..64).map(|_| scheduler.frames(1.0 / 64.0)).sum();
+        assert_eq!(total, 60);
+    }
+
+    #[test]
+    fn caps_catch_up() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.frames(10.0), 4);
+        // the rest of the backlog is dropped
+        assert_eq!(scheduler.frames(0.0), 0);
+
+        // faster speeds may catch up proportionally more
+        scheduler.set_speed(4.0);
+        assert_eq!(scheduler.frames(10.0), 16);
+    }
+
+    #[test]
+    fn speed() {
+        let mut scheduler = Scheduler::new();
+        scheduler.set_speed(2.0);
+        assert_eq!(scheduler.frames(1.0 / 30.0), 4);
+        scheduler.set_speed(0.5);
+        assert_eq!(scheduler.frames(1.0 / 30.0), 1);
+
+        scheduler.set_speed(100.0);
+        assert_eq!(scheduler.speed, Scheduler::MAX_SPEED);
+        scheduler.set_speed(0.0);
+        assert_eq!(scheduler.speed, Scheduler::MIN_SPEED);
+        scheduler.set_speed(-1.0);
+        assert_eq!(scheduler.speed, Scheduler::MIN_SPEED);
+    }
+
+    #[test]
+    fn bad_elapsed() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.frames(0.0), 0);
+        assert_eq!(scheduler.frames(1.0 / 120.0), 0);
+        // none of these run or take away frames
+        for elapsed in [0.0, -1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(scheduler.frames(elapsed), 0, "{}", elapsed);
+        }
+        assert_eq!(scheduler.frames(1.0 / 120.0), 1);
+    }
+}
@@ -1,4 +1,4 @@
-#![allow(non_upper_case_globals)]
+#![allow(non_upper_case_globals, dead_code)]
 use macroquad::{
     miniquad::{BlendFactor, BlendState, BlendValue, Equation},
     prelude::*,
@@ -9,7 +9,7 @@ use crate::postprocessing::GfxShader;
 lazy_static! {
     pub static ref DEFAULT_SHADER: GfxShader = GfxShader::new(0.0, 0.0, *DefaultFrag, false);
     pub static ref DefaultFrag: Material = load_material(
-        &VERTEX_SHADER,
+        VERTEX_SHADER,
         "#version 100
          precision lowp float;
          varying vec4 color;
@@ -31,8 +31,8 @@ lazy_static! {
     )
     .unwrap();
     pub static ref ChromaticAberration: Material = load_material(
-        &VERTEX_SHADER,
-        &CHROMATIC_ABERRATION_FRAG,
+        VERTEX_SHADER,
+        CHROMATIC_ABERRATION_FRAG,
         MaterialParams {
             pipeline_params: PipelineParams {
                 color_blend: Some(BlendState::new(
@@ -47,8 +47,8 @@ lazy_static! {
     )
     .unwrap();
     pub static ref GaussianBlur: Material = load_material(
-        &VERTEX_SHADER,
-        &GAUSSIAN_BLUR_FRAG,
+        VERTEX_SHADER,
+        GAUSSIAN_BLUR_FRAG,
         MaterialParams {
             pipeline_params: PipelineParams {
                 color_blend: Some(BlendState::new(
@@ -63,8 +63,8 @@ lazy_static! {
     )
     .unwrap();
     pub static ref Bloom: Material = load_material(
-        &VERTEX_SHADER,
-        &BLOOM_FRAG,
+        VERTEX_SHADER,
+        BLOOM_FRAG,
         MaterialParams {
             pipeline_params: PipelineParams {
                 color_blend: Some(BlendState::new(
@@ -0,0 +1,55 @@
+use std::{fs, path::PathBuf};
+
+use chirp::chip8::Chip8;
+use macroquad::prelude::{is_key_pressed, KeyCode};
+
+// Quick-save slots for the native frontend, kept on disk so a session can be
+// resumed after quitting.
+//   F5 save, F9 load, F6/F7 previous/next slot
+pub struct QuickSlots {
+    slot: usize,
+    pub status: Option<String>, // last message to show on screen
+}
+
+impl QuickSlots {
+    pub const SLOTS: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            slot: 0,
+            status: None,
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        PathBuf::from(format!("chirp-slot{}.state", self.slot + 1))
+    }
+
+    pub fn update(&mut self, chippy: &mut Chip8) {
+        if is_key_pressed(KeyCode::F6) {
+            self.slot = (self.slot + Self::SLOTS - 1) % Self::SLOTS;
+            self.status = Some(format!("slot {}", self.slot + 1));
+        }
+        if is_key_pressed(KeyCode::F7) {
+            self.slot = (self.slot + 1) % Self::SLOTS;
+            self.status = Some(format!("slot {}", self.slot + 1));
+        }
+
+        if is_key_pressed(KeyCode::F5) {
+            self.status = Some(match fs::write(self.path(), chippy.save_state()) {
+                Ok(()) => format!("saved slot {}", self.slot + 1),
+                Err(e) => format!("save failed: {}", e),
+            });
+        }
+
+        if is_key_pressed(KeyCode::F9) {
+            self.status = Some(match fs::read(self.path()) {
+                Ok(data) => match chippy.load_state(&data) {
+                    Ok(()) => format!("loaded slot {}", self.slot + 1),
+                    Err(e) => format!("load failed: {}", e),
+                },
+                Err(e) => format!("load failed: {}", e),
+            });
+        }
+    }
+}
@@ -0,0 +1,493 @@
+// Framebuffer snapshots, to lock in what a ROM draws.
+//
+// A `Snapshot` is a copy of the visible screen, one palette index per pixel
+// (see `Display::pixel`). It can be written as:
+//   ASCII  one line per row, '.' is off, '#' is plane 1 and any other
+//          combination of planes is its hex digit
+//   PBM    binary (P4) bitmap, any lit pixel is black
+//   PNG    8-bit indexed colour, 1:1 pixels
+// ASCII and PBM can be read back as references to `diff` against. PBM keeps
+// no plane information, so compare it with `Snapshot::mono`.
+use std::fmt;
+
+use crate::display::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Ascii,
+    Pbm,
+    Png,
+}
+
+impl SnapshotFormat {
+    pub const NAMES: [&'static str; 3] = ["ascii", "pbm", "png"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Self::Ascii),
+            "pbm" => Some(Self::Pbm),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    // guessed from a file extension, ASCII for anything unknown
+    pub fn from_path(path: &str) -> Self {
+        let ext = path
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("pbm") => Self::Pbm,
+            Some("png") => Self::Png,
+            _ => Self::Ascii,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    Empty,
+    Ragged { row: usize },           // rows of different widths
+    BadPixel { x: usize, y: usize }, // a character that isn't a pixel
+    BadHeader,
+    Truncated,
+    Unreadable(SnapshotFormat), // there's no reader for this format
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "snapshot is empty"),
+            Self::Ragged { row } => write!(f, "row {} has a different width", row),
+            Self::BadPixel { x, y } => write!(f, "bad pixel at {},{}", x, y),
+            Self::BadHeader => write!(f, "not a PBM file"),
+            Self::Truncated => write!(f, "snapshot is truncated"),
+            Self::Unreadable(format) => write!(f, "can't read {:?} snapshots", format),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>, // palette index of each pixel, row by row
+}
+
+impl Snapshot {
+    // PNG colours, the frontend's default palette on black
+    pub const PALETTE: [[u8; 3]; 16] = [
+        [0x00, 0x00, 0x00],
+        [0xFF, 0x66, 0xC9],
+        [0x66, 0xCC, 0xFF],
+        [0xFF, 0xFF, 0xFF],
+        [0xFF, 0xCC, 0x33],
+        [0xFF, 0x99, 0x66],
+        [0x66, 0xFF, 0x99],
+        [0xCC, 0xCC, 0xCC],
+        [0x99, 0x66, 0xFF],
+        [0xFF, 0x4D, 0x4D],
+        [0x4D, 0x99, 0xFF],
+        [0xE6, 0xE6, 0x80],
+        [0x80, 0x4D, 0x33],
+        [0x4D, 0x80, 0x4D],
+        [0x4D, 0x4D, 0x80],
+        [0x99, 0x99, 0x99],
+    ];
+
+    pub fn from_display(display: &Display) -> Self {
+        let (width, height) = (display.width(), display.height());
+        Self {
+            width: width as usize,
+            height: height as usize,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| display.pixel(x, y))
+                .collect(),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x + y * self.width]
+    }
+
+    // every lit pixel as plane 1, to compare with a PBM
+    pub fn mono(&self) -> Self {
+        Self {
+            pixels: self.pixels.iter().map(|&p| (p != 0) as u8).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn encode(&self, format: SnapshotFormat, palette: &[[u8; 3]; 16]) -> Vec<u8> {
+        match format {
+            SnapshotFormat::Ascii => self.to_ascii().into_bytes(),
+            SnapshotFormat::Pbm => self.to_pbm(),
+            SnapshotFormat::Png => self.to_png(palette),
+        }
+    }
+
+    pub fn decode(data: &[u8], format: SnapshotFormat) -> Result<Self, SnapshotError> {
+        match format {
+            SnapshotFormat::Ascii => Self::from_ascii(&String::from_utf8_lossy(data)),
+            SnapshotFormat::Pbm => Self::from_pbm(data),
+            SnapshotFormat::Png => Err(SnapshotError::Unreadable(format)),
+        }
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            out.extend(row.iter().map(|&p| match p {
+                0 => '.',
+                1 => '#',
+                p => char::from_digit(p as u32, 16).unwrap(),
+            }));
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn from_ascii(text: &str) -> Result<Self, SnapshotError> {
+        let rows: Vec<&str> = text.lines().filter(|row| !row.is_empty()).collect();
+        let width = rows.first().ok_or(SnapshotError::Empty)?.chars().count();
+        let mut pixels = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(SnapshotError::Ragged { row: y });
+            }
+            for (x, c) in row.chars().enumerate() {
+                pixels.push(match c {
+                    '.' => 0,
+                    '#' => 1,
+                    c => c.to_digit(16).ok_or(SnapshotError::BadPixel { x, y })? as u8,
+                });
+            }
+        }
+        Ok(Self {
+            width,
+            height: rows.len(),
+            pixels,
+        })
+    }
+
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut out = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        for row in self.pixels.chunks(self.width) {
+            // rows are padded to whole bytes
+            for byte in row.chunks(8) {
+                let bits = byte
+                    .iter()
+                    .enumerate()
+                    .fold(0, |bits, (i, &p)| bits | ((p != 0) as u8) << (7 - i));
+                out.push(bits);
+            }
+        }
+        out
+    }
+
+    // reads the binary (P4) and plain (P1) variants
+    pub fn from_pbm(data: &[u8]) -> Result<Self, SnapshotError> {
+        // the header is three whitespace separated fields, # starts a comment
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 3 {
+            while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+                if data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(SnapshotError::Truncated);
+            }
+            fields.push(
+                std::str::from_utf8(&data[start..pos]).map_err(|_| SnapshotError::BadHeader)?,
+            );
+        }
+        let size = |s: &str| s.parse::<usize>().ok().filter(|&n| n > 0);
+        let (width, height) = match (size(fields[1]), size(fields[2])) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(SnapshotError::BadHeader),
+        };
+        // the size is untrusted, it mustn't overflow
+        let area = width.checked_mul(height).ok_or(SnapshotError::BadHeader)?;
+
+        let pixels = match fields[0] {
+            "P4" => {
+                // a single whitespace byte separates the header from the bits
+                let bits = data.get(pos + 1..).unwrap_or_default();
+                let stride = width.div_ceil(8);
+                let len = stride.checked_mul(height).ok_or(SnapshotError::BadHeader)?;
+                if bits.len() < len {
+                    return Err(SnapshotError::Truncated);
+                }
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| (bits[y * stride + x / 8] >> (7 - x % 8)) & 1)
+                    .collect()
+            }
+            "P1" => {
+                let pixels: Vec<u8> = data[pos..]
+                    .iter()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .map(|&b| match b {
+                        b'0' => Ok(0),
+                        b'1' => Ok(1),
+                        _ => Err(SnapshotError::BadHeader),
+                    })
+                    .take(area)
+                    .collect::<Result<_, _>>()?;
+                if pixels.len() < area {
+                    return Err(SnapshotError::Truncated);
+                }
+                pixels
+            }
+            _ => return Err(SnapshotError::BadHeader),
+        };
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // an uncompressed PNG, palette index n is `palette[n]`
+    pub fn to_png(&self, palette: &[[u8; 3]; 16]) -> Vec<u8> {
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bits per pixel, indexed colour, default compression/filter, no interlace
+        header.extend([8, 3, 0, 0, 0]);
+        png_chunk(&mut out, b"IHDR", &header);
+        png_chunk(&mut out, b"PLTE", palette.as_flattened());
+
+        // each row starts with filter type 0 (none)
+        let mut raw = Vec::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            raw.extend(row);
+        }
+        png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    // compare with a reference, `expected`
+    pub fn diff(&self, expected: &Snapshot) -> Diff {
+        if (self.width, self.height) != (expected.width, expected.height) {
+            return Diff::Size {
+                expected: (expected.width, expected.height),
+                actual: (self.width, self.height),
+            };
+        }
+        let count = self
+            .pixels
+            .iter()
+            .zip(&expected.pixels)
+            .filter(|(a, b)| a != b)
+            .count();
+        if count == 0 {
+            return Diff::Same;
+        }
+
+        // the actual screen, with changed pixels marked
+        let mut map = String::with_capacity((self.width + 1) * self.height);
+        for (actual, expected) in self
+            .pixels
+            .chunks(self.width)
+            .zip(expected.pixels.chunks(self.width))
+        {
+            map.extend(actual.iter().zip(expected).map(|(&a, &e)| match (a, e) {
+                (a, e) if a == e && a == 0 => '.',
+                (a, e) if a == e => '#',
+                (_, 0) => '+', // lit, but shouldn't be
+                (0, _) => '-', // should be lit
+                _ => 'x',      // lit on the wrong planes
+            }));
+            map.push('\n');
+        }
+        Diff::Pixels { count, map }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diff {
+    Same,
+    Size {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    Pixels {
+        count: usize, // pixels that differ
+        map: String,  // ASCII art of the screen, see `Snapshot::diff`
+    },
+}
+
+impl Diff {
+    pub fn is_same(&self) -> bool {
+        *self == Self::Same
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Same => write!(f, "snapshots match"),
+            Self::Size { expected, actual } => write!(
+                f,
+                "screen is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Self::Pixels { count, map } => {
+                writeln!(
+                    f,
+                    "{} pixels differ (+ unexpected, - missing, x wrong planes)",
+                    count
+                )?;
+                write!(f, "{}", map)
+            }
+        }
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+// zlib stream of uncompressed deflate blocks, snapshots are too small to
+// bother compressing
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    out.extend(((b << 16) | a).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> Snapshot {
+        let (width, height) = (10, 3);
+        Snapshot {
+            width,
+            height,
+            pixels: (0..width * height).map(|i| (i % 3) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let snapshot = checker();
+        assert_eq!(snapshot.to_ascii().lines().next(), Some(".#2.#2.#2."));
+        assert_eq!(Snapshot::from_ascii(&snapshot.to_ascii()), Ok(snapshot));
+        assert_eq!(
+            Snapshot::from_ascii("..\n...\n"),
+            Err(SnapshotError::Ragged { row: 1 })
+        );
+        assert_eq!(
+            Snapshot::from_ascii("..\n.?\n"),
+            Err(SnapshotError::BadPixel { x: 1, y: 1 })
+        );
+    }
+
+    #[test]
+    fn pbm_round_trip() {
+        let snapshot = checker();
+        let pbm = snapshot.to_pbm();
+        assert!(pbm.starts_with(b"P4\n10 3\n"));
+        assert_eq!(Snapshot::from_pbm(&pbm), Ok(snapshot.mono()));
+
+        let plain = b"P1\n# a comment\n3 2\n0 1 0\n1 0 1\n";
+        let snapshot = Snapshot::from_pbm(plain).unwrap();
+        assert_eq!(snapshot.pixels, [0, 1, 0, 1, 0, 1]);
+        assert_eq!(
+            Snapshot::from_pbm(b"P4\n8 2\n\xFF"),
+            Err(SnapshotError::Truncated)
+        );
+    }
+
+    #[test]
+    fn pbm_huge_header() {
+        for kind in ["P4", "P1"] {
+            let header = format!("{}\n{} {}\n", kind, usize::MAX / 2, 3);
+            assert_eq!(
+                Snapshot::from_pbm(header.as_bytes()),
+                Err(SnapshotError::BadHeader)
+            );
+        }
+        assert!(Snapshot::from_pbm(b"P4 4294967295 4294967295\n").is_err());
+    }
+
+    #[test]
+    fn png_checksums() {
+        // the CRC of the IEND chunk is fixed
+        let png = checker().to_png(&Snapshot::PALETTE);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        // zlib's own test vector
+        let zlib = zlib_stored(b"Wikipedia");
+        assert_eq!(&zlib[zlib.len() - 4..], &0x11E6_0398u32.to_be_bytes());
+    }
+
+    #[test]
+    fn diff() {
+        let expected = checker();
+        assert!(expected.diff(&expected).is_same());
+
+        let mut actual = expected.clone();
+        actual.pixels[0] = 1;
+        actual.pixels[1] = 0;
+        actual.pixels[2] = 1;
+        match actual.diff(&expected) {
+            Diff::Pixels { count, map } => {
+                assert_eq!(count, 3);
+                assert_eq!(map.lines().next(), Some("+-x.##.##."));
+            }
+            diff => panic!("{:?}", diff),
+        }
+
+        let small = Snapshot::from_ascii("..\n").unwrap();
+        assert!(matches!(small.diff(&expected), Diff::Size { .. }));
+    }
+}
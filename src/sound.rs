@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use chirp::{
+    audio::{Beeper, Waveform},
+    chip8::Chip8,
+};
+use macroquad::{
+    audio::{
+        load_sound_from_bytes, play_sound, set_sound_volume, stop_sound, PlaySoundParams, Sound,
+    },
+    prelude::{is_key_pressed, KeyCode},
+};
+
+// Plays the sound timer through macroquad audio.
+//   M mute, -/= volume down/up
+pub struct Speaker {
+    pub beeper: Beeper,
+    sounds: HashMap<Waveform, Sound>, // macroquad can't unload sounds, so keep them
+    playing: Option<Waveform>,
+    volume: f32, // volume the current sound was started with
+}
+
+impl Speaker {
+    pub fn new(beeper: Beeper) -> Self {
+        Self {
+            beeper,
+            sounds: HashMap::new(),
+            playing: None,
+            volume: 0.0,
+        }
+    }
+
+    pub async fn update(&mut self, chippy: &Chip8) {
+        if is_key_pressed(KeyCode::M) {
+            self.beeper.muted = !self.beeper.muted;
+        }
+        if is_key_pressed(KeyCode::Minus) {
+            self.beeper.volume_down();
+        }
+        if is_key_pressed(KeyCode::Equal) {
+            self.beeper.volume_up();
+        }
+
+        let wanted = self
+            .beeper
+            .active(chippy)
+            .then(|| self.beeper.waveform(chippy));
+
+        if wanted == self.playing {
+            if let Some(waveform) = wanted {
+                if self.volume != self.beeper.volume {
+                    self.volume = self.beeper.volume;
+                    set_sound_volume(self.sounds[&waveform], self.volume);
+                }
+            }
+            return;
+        }
+
+        if let Some(waveform) = self.playing.take() {
+            stop_sound(self.sounds[&waveform]);
+        }
+
+        if let Some(waveform) = wanted {
+            let sound = match self.sounds.get(&waveform) {
+                Some(&sound) => sound,
+                None => match load_sound_from_bytes(&waveform.wav()).await {
+                    Ok(sound) => *self.sounds.entry(waveform).or_insert(sound),
+                    Err(_) => return,
+                },
+            };
+            self.volume = self.beeper.volume;
+            play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: true,
+                    volume: self.volume,
+                },
+            );
+            self.playing = Some(waveform);
+        }
+    }
+}
@@ -4,20 +4,58 @@ pub struct Stack {
 }
 
 impl Stack {
+    pub const DEPTH: usize = 16;
+
     pub fn new() -> Self {
         Stack {
-            stack: [0x00_u16; 16],
+            stack: [0x00_u16; Self::DEPTH],
             pointer: 0,
         }
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.pointer -= 1;
-        self.stack[self.pointer]
+    // None if the stack is empty
+    pub fn pop(&mut self) -> Option<u16> {
+        self.pointer = self.pointer.checked_sub(1)?;
+        Some(self.stack[self.pointer])
     }
 
-    pub fn push(&mut self, v: u16) {
+    // false if the stack is full
+    pub fn push(&mut self, v: u16) -> bool {
+        if self.pointer >= Self::DEPTH {
+            return false;
+        }
         self.stack[self.pointer] = v;
         self.pointer += 1;
+        true
+    }
+
+    pub fn pop_wrapping(&mut self) -> u16 {
+        self.pointer = (self.pointer + Self::DEPTH - 1) % Self::DEPTH;
+        self.stack[self.pointer]
+    }
+
+    pub fn push_wrapping(&mut self, v: u16) {
+        self.stack[self.pointer % Self::DEPTH] = v;
+        self.pointer = (self.pointer + 1) % Self::DEPTH;
+    }
+
+    pub fn depth(&self) -> usize {
+        self.pointer
+    }
+
+    // every slot of the stack, including ones above the pointer
+    pub fn slots(&self) -> &[u16; Self::DEPTH] {
+        &self.stack
+    }
+
+    pub fn restore(&mut self, slots: [u16; Self::DEPTH], pointer: usize) {
+        self.stack = slots;
+        self.pointer = pointer.min(Self::DEPTH);
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
     }
 }
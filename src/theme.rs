@@ -0,0 +1,175 @@
+// Screen colours and pixel shapes, remembered between runs. The native build
+// keeps them in a settings file in the user's config directory, the web page
+// in its local storage.
+use macroquad::color::Color;
+
+// how each lit pixel is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelStyle {
+    #[default]
+    Square, // touching squares
+    Gaps,    // squares with a gap around each
+    Grid,    // squares, with grid lines over the whole screen
+    Rounded, // squares with rounded corners
+}
+
+impl PixelStyle {
+    pub const NAMES: [&'static str; 4] = ["square", "gaps", "grid", "rounded"];
+    const ALL: [Self; 4] = [Self::Square, Self::Gaps, Self::Grid, Self::Rounded];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let i = Self::NAMES.iter().position(|&n| n == name)?;
+        Some(Self::ALL[i])
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn next(&self) -> Self {
+        Self::ALL[(*self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub foreground: Color, // plane 1, other XO-CHIP planes keep their colours
+    pub background: Color,
+    pub style: PixelStyle,
+}
+
+impl Theme {
+    // name, foreground and background of the built in palettes
+    pub const PALETTES: [(&'static str, u32, u32); 5] = [
+        ("chirp", 0xFF66C9, 0x000000),
+        ("green", 0x33FF66, 0x001108), // green phosphor
+        ("amber", 0xFFB000, 0x1A0E00), // amber phosphor
+        ("gameboy", 0x0F380F, 0x9BBC0F),
+        ("contrast", 0xFFFFFF, 0x000000),
+    ];
+
+    pub fn new() -> Self {
+        Self::palette("chirp").unwrap()
+    }
+
+    // a built in palette with square pixels
+    pub fn palette(name: &str) -> Option<Self> {
+        let &(_, fg, bg) = Self::PALETTES.iter().find(|&&(n, _, _)| n == name)?;
+        Some(Self {
+            foreground: from_rgb(fg),
+            background: from_rgb(bg),
+            style: PixelStyle::default(),
+        })
+    }
+
+    // switch to the next built in palette, keeping the pixel style
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn next_palette(&mut self) -> &'static str {
+        let current = Self::PALETTES.iter().position(|&(_, fg, bg)| {
+            from_rgb(fg) == self.foreground && from_rgb(bg) == self.background
+        });
+        let (name, fg, bg) = match current {
+            Some(i) => Self::PALETTES[(i + 1) % Self::PALETTES.len()],
+            None => Self::PALETTES[0],
+        };
+        self.foreground = from_rgb(fg);
+        self.background = from_rgb(bg);
+        name
+    }
+
+    // `key=value` lines, unknown or bad lines are skipped
+    pub fn settings(&self) -> String {
+        format!(
+            "fg={}\nbg={}\npixels={}\n",
+            to_hex(self.foreground),
+            to_hex(self.background),
+            self.style.name()
+        )
+    }
+
+    pub fn from_settings(text: &str) -> Self {
+        let mut theme = Self::new();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let value = value.trim();
+            match key.trim() {
+                "fg" => theme.foreground = parse_colour(value).unwrap_or(theme.foreground),
+                "bg" => theme.background = parse_colour(value).unwrap_or(theme.background),
+                "pixels" => theme.style = PixelStyle::from_name(value).unwrap_or(theme.style),
+                _ => {}
+            }
+        }
+        theme
+    }
+
+    // the saved theme, or the default one
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load() -> Self {
+        settings_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| Self::from_settings(&text))
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> Result<(), String> {
+        let path = settings_path().ok_or("no config directory")?;
+        let write = || {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, self.settings())
+        };
+        write().map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// $XDG_CONFIG_HOME/chirp/display, falling back to ~/.config or %APPDATA%
+#[cfg(not(target_arch = "wasm32"))]
+fn settings_path() -> Option<std::path::PathBuf> {
+    use std::{env::var_os, path::PathBuf};
+
+    let dir = var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("chirp").join("display"))
+}
+
+fn from_rgb(rgb: u32) -> Color {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Color::from_rgba(r, g, b, 255)
+}
+
+fn to_hex(colour: Color) -> String {
+    let [r, g, b, _]: [u8; 4] = colour.into();
+    format!("{:02X}{:02X}{:02X}", r, g, b)
+}
+
+// RRGGBB, optionally prefixed with #
+pub fn parse_colour(s: &str) -> Result<Color, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    // from_str_radix alone would take a sign, as in +FFFFF
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("bad colour {}, expected RRGGBB", s));
+    }
+    Ok(from_rgb(u32::from_str_radix(hex, 16).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours() {
+        assert_eq!(parse_colour("#FF8000").unwrap(), from_rgb(0xFF8000));
+        assert_eq!(parse_colour("00ff80").unwrap(), from_rgb(0x00FF80));
+        for bad in ["+FFFFF", "-00000", "#FFF", "FF80001", "GG0000", ""] {
+            assert!(parse_colour(bad).is_err(), "{}", bad);
+        }
+    }
+}
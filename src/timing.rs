@@ -0,0 +1,73 @@
+// How long instructions take.
+//
+// The machine keeps a clock that advances by the cost of every instruction
+// and runs the 60Hz timers from it, so timers stay exact whatever `hz` is.
+// With `Timing::Instructions` every instruction costs the same and `hz` of
+// them run per second. `Timing::Cycles` models the COSMAC VIP instead: the
+// clock runs in microseconds, instructions cost roughly what they took on
+// the VIP's interpreter and DXYN waits for the next vblank before drawing.
+use crate::instruction::Instruction;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    #[default]
+    Instructions, // `hz` instructions a second
+    Cycles, // per-instruction COSMAC VIP timing, `hz` is ignored
+}
+
+impl Timing {
+    pub const NAMES: [&'static str; 2] = ["instructions", "cycles"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "instructions" => Some(Self::Instructions),
+            "cycles" => Some(Self::Cycles),
+            _ => None,
+        }
+    }
+
+    // clock units per second
+    pub fn rate(&self, hz: i32) -> i32 {
+        match self {
+            Self::Instructions => hz.max(1),
+            Self::Cycles => 1_000_000,
+        }
+    }
+
+    // clock units `instruction` takes, not counting a wait for vblank
+    pub fn cost(&self, instruction: &Instruction) -> i32 {
+        match self {
+            Self::Instructions => 1,
+            Self::Cycles => vip_microseconds(instruction),
+        }
+    }
+}
+
+// Approximate times of the VIP's interpreter. Extensions the VIP didn't have
+// cost as much as their closest relative.
+fn vip_microseconds(instruction: &Instruction) -> i32 {
+    use Instruction::*;
+
+    match *instruction {
+        Clear => 109,
+        Return | Jump { .. } | Call { .. } | JumpOffset { .. } => 105,
+        SkipEqImm { .. } | SkipNeImm { .. } | SetI { .. } => 55,
+        SkipEq { .. } | SkipNe { .. } | SkipKey { .. } | SkipNotKey { .. } => 73,
+        SetImm { .. } => 27,
+        AddImm { .. } | GetDelay { .. } | SetDelay { .. } | SetSound { .. } => 45,
+        Set { .. } | Or { .. } | And { .. } | Xor { .. } | Add { .. } | Sub { .. } => 200,
+        ShiftRight { .. } | SubReverse { .. } | ShiftLeft { .. } => 200,
+        Random { .. } => 164,
+        // after the vblank wait, the sprite is drawn a row at a time
+        Draw { n, .. } => 68 + 102 * n as i32,
+        DrawBig { .. } => 68 + 2 * 102 * 16,
+        AddI { .. } => 86,
+        Font { .. } | BigFont { .. } => 91,
+        Bcd { .. } => 927,
+        Store { x } | Load { x } => 605 + 64 * x as i32,
+        SaveRange { x, y } | LoadRange { x, y } => 605 + 64 * x.abs_diff(y) as i32,
+        WaitKey { .. } => 45,
+        SetILong { .. } => 110,
+        _ => 45,
+    }
+}
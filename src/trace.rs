@@ -0,0 +1,357 @@
+// Execution trace logging.
+//
+// Set `Chip8::trace` to a `Tracer` and every instruction `Chip8::cycle`
+// executes is logged with the machine state just before it ran, timers
+// included. A DXYN waiting for vblank (`Quirks::display_wait`, VIP timing) is
+// logged once, when it draws. The text format is one line per instruction,
+// made to be diffed against logs from other emulators:
+//   0200: 00E0 V 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 DT 00 ST 00 SP 0
+//
+// The binary format is "CH8T" magic, u8 version and then 25 byte records
+// (all integers little endian):
+//   pc u16, opcode u16, v[16], i u16, delay u8, sound u8, stack depth u8
+use std::{
+    fmt,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use crate::chip8::Chip8;
+
+const MAGIC: &[u8; 4] = b"CH8T";
+pub const VERSION: u8 = 1;
+const RECORD_SIZE: usize = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+// the state of the machine as an instruction is about to run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack_depth: u8,
+}
+
+impl TraceEntry {
+    pub fn capture(chippy: &Chip8, opcode: u16) -> Self {
+        Self {
+            pc: chippy.pc,
+            opcode,
+            v: chippy.v,
+            i: chippy.i,
+            delay_timer: chippy.delay_timer,
+            sound_timer: chippy.sound_timer,
+            stack_depth: chippy.stack.depth() as u8,
+        }
+    }
+
+    // a line in the text format
+    pub fn parse(line: &str) -> Option<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let [pc, opcode, "V", rest @ ..] = words.as_slice() else {
+            return None;
+        };
+        let [v @ .., "I", i, "DT", dt, "ST", st, "SP", sp] = rest else {
+            return None;
+        };
+        let hex = |word: &str, len: usize| {
+            (word.len() == len)
+                .then(|| u16::from_str_radix(word, 16).ok())
+                .flatten()
+        };
+
+        let mut entry = Self {
+            pc: hex(pc.strip_suffix(':')?, 4)?,
+            opcode: hex(opcode, 4)?,
+            v: [0; 16],
+            i: hex(i, 4)?,
+            delay_timer: hex(dt, 2)? as u8,
+            sound_timer: hex(st, 2)? as u8,
+            stack_depth: sp.parse().ok()?,
+        };
+        if v.len() != 16 {
+            return None;
+        }
+        for (reg, word) in entry.v.iter_mut().zip(v) {
+            *reg = hex(word, 2)? as u8;
+        }
+        Some(entry)
+    }
+
+    fn write_binary(&self, out: &mut Vec<u8>) {
+        out.extend(self.pc.to_le_bytes());
+        out.extend(self.opcode.to_le_bytes());
+        out.extend(self.v);
+        out.extend(self.i.to_le_bytes());
+        out.extend([self.delay_timer, self.sound_timer, self.stack_depth]);
+    }
+
+    fn read_binary(record: &[u8]) -> Self {
+        Self {
+            pc: u16::from_le_bytes([record[0], record[1]]),
+            opcode: u16::from_le_bytes([record[2], record[3]]),
+            v: record[4..20].try_into().unwrap(),
+            i: u16::from_le_bytes([record[20], record[21]]),
+            delay_timer: record[22],
+            sound_timer: record[23],
+            stack_depth: record[24],
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}: {:04X} V", self.pc, self.opcode)?;
+        for v in self.v {
+            write!(f, " {:02X}", v)?;
+        }
+        write!(
+            f,
+            " I {:04X} DT {:02X} ST {:02X} SP {}",
+            self.i, self.delay_timer, self.sound_timer, self.stack_depth
+        )
+    }
+}
+
+// a text or binary trace, as written by `Tracer`
+pub fn read_trace(data: &[u8]) -> Option<Vec<TraceEntry>> {
+    if let Some(records) = data.strip_prefix(MAGIC) {
+        let (&version, records) = records.split_first()?;
+        if version != VERSION || records.len() % RECORD_SIZE != 0 {
+            return None;
+        }
+        Some(
+            records
+                .chunks(RECORD_SIZE)
+                .map(TraceEntry::read_binary)
+                .collect(),
+        )
+    } else {
+        std::str::from_utf8(data)
+            .ok()?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(TraceEntry::parse)
+            .collect()
+    }
+}
+
+// writes trace entries for the instructions that pass its filters
+pub struct Tracer {
+    pub format: TraceFormat,
+    pub range: RangeInclusive<u16>, // only log instructions at these addresses
+    pub classes: u16,               // bit n logs opcodes nXXX, see `parse_classes`
+    out: Box<dyn Write + Send>,
+    started: bool,            // binary header written
+    error: Option<io::Error>, // the first write error, logging stops there
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        Self {
+            format,
+            range: 0..=0xFFFF,
+            classes: 0xFFFF,
+            out,
+            started: false,
+            error: None,
+        }
+    }
+
+    pub fn wants(&self, pc: u16, opcode: u16) -> bool {
+        self.range.contains(&pc) && self.classes & (1 << (opcode >> 12)) != 0
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() || !self.wants(entry.pc, entry.opcode) {
+            return;
+        }
+        let result = match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", entry),
+            TraceFormat::Binary => {
+                let mut record = Vec::with_capacity(MAGIC.len() + 1 + RECORD_SIZE);
+                if !self.started {
+                    record.extend(MAGIC);
+                    record.push(VERSION);
+                    self.started = true;
+                }
+                entry.write_binary(&mut record);
+                self.out.write_all(&record)
+            }
+        };
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    // flush the log, reporting the first error hit while writing it; nothing
+    // more is logged after an error so the tracer should be dropped
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+}
+
+// opcode classes by their first hex digit, like "d,f" or "0-3,8"
+pub fn parse_classes(s: &str) -> Option<u16> {
+    let digit = |s: &str| u8::from_str_radix(s.trim(), 16).ok().filter(|&d| d < 16);
+    let mut classes = 0;
+    for part in s.split(',') {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (digit(start)?, digit(end)?),
+            None => (digit(part)?, digit(part)?),
+        };
+        for d in start..=end {
+            classes |= 1 << d;
+        }
+    }
+    Some(classes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::roms;
+
+    // a writer the test can read back after the tracer is done with it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // the trace of brix's first frames
+    fn trace(format: TraceFormat, setup: impl Fn(&mut Tracer)) -> Vec<u8> {
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), format);
+        setup(&mut tracer);
+        let mut chippy = Chip8::init(1000);
+        chippy.load_rom(roms::Brix).unwrap();
+        chippy.trace = Some(tracer);
+        for _ in 0..5 {
+            chippy.run_frame().unwrap();
+        }
+        chippy.trace.unwrap().flush().unwrap();
+        let data = out.0.lock().unwrap().clone();
+        data
+    }
+
+    #[test]
+    fn text_and_binary_agree() {
+        let text = trace(TraceFormat::Text, |_| {});
+        let binary = trace(TraceFormat::Binary, |_| {});
+        assert!(binary.starts_with(b"CH8T\x01"));
+        assert_eq!((binary.len() - 5) % RECORD_SIZE, 0);
+
+        let entries = read_trace(&text).unwrap();
+        assert_eq!(read_trace(&binary).unwrap(), entries);
+        assert_eq!(entries.len(), (binary.len() - 5) / RECORD_SIZE);
+        assert_eq!(
+            entries[0].to_string(),
+            "0200: 6E05 V 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000 DT 00 ST 00 SP 0"
+        );
+        assert_eq!(entries[1].v[0xE], 0x05);
+    }
+
+    #[test]
+    fn draw_waits_are_logged_once() {
+        // DT = 5, then two draws, the second waits for the next frame
+        let rom = [0x60, 0x05, 0xF0, 0x15, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x08];
+        let out = Shared::default();
+        let mut chippy = Chip8::init(1000);
+        chippy.load_rom(&rom).unwrap();
+        chippy.quirks.display_wait = true;
+        chippy.trace = Some(Tracer::new(Box::new(out.clone()), TraceFormat::Binary));
+        for _ in 0..2 {
+            chippy.run_frame().unwrap();
+        }
+        chippy.trace.unwrap().flush().unwrap();
+
+        let entries = read_trace(&out.0.lock().unwrap()).unwrap();
+        let draws: Vec<_> = entries.iter().filter(|e| e.opcode == 0xD005).collect();
+        assert_eq!(draws.len(), 2);
+        // each with the timer it drew with, the second a frame later
+        assert_eq!((draws[0].pc, draws[0].delay_timer), (0x204, 5));
+        assert_eq!((draws[1].pc, draws[1].delay_timer), (0x206, 4));
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entry = TraceEntry {
+            pc: 0xFFFE,
+            opcode: 0xF000,
+            v: std::array::from_fn(|i| (i * 17) as u8),
+            i: 0xBEEF,
+            delay_timer: 0x3C,
+            sound_timer: 0xFF,
+            stack_depth: 16,
+        };
+        for format in [TraceFormat::Text, TraceFormat::Binary] {
+            let out = Shared::default();
+            let mut tracer = Tracer::new(Box::new(out.clone()), format);
+            tracer.record(&entry);
+            tracer.record(&entry);
+            tracer.flush().unwrap();
+            let data = out.0.lock().unwrap().clone();
+            assert_eq!(read_trace(&data), Some(vec![entry, entry]), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn bad_traces() {
+        assert_eq!(read_trace(b"CH8T\x02"), None);
+        assert_eq!(read_trace(b"CH8T\x01\x00\x02"), None);
+        assert_eq!(read_trace(b"0200: 00E0 V 00"), None);
+        assert_eq!(read_trace(b""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(parse_classes("d,f"), Some(0xA000));
+        assert_eq!(parse_classes("0-3,8"), Some(0x010F));
+        assert_eq!(parse_classes("g"), None);
+
+        let all = read_trace(&trace(TraceFormat::Text, |_| {})).unwrap();
+        let data = trace(TraceFormat::Binary, |tracer| {
+            tracer.range = 0x200..=0x21F;
+            tracer.classes = parse_classes("6,a").unwrap();
+        });
+        let filtered = read_trace(&data).unwrap();
+        let expected: Vec<TraceEntry> = all
+            .into_iter()
+            .filter(|e| (0x200..=0x21F).contains(&e.pc))
+            .filter(|e| matches!(e.opcode >> 12, 0x6 | 0xA))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(filtered, expected);
+    }
+}
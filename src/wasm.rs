@@ -1,22 +1,63 @@
 #![cfg(target_arch = "wasm32")]
 
-use crate::chip8::Chip8;
-use crate::roms::get_rom;
+use chirp::assembler::parse_symbols;
+use chirp::audio::{Beeper, Waveform};
+use chirp::chip8::Chip8;
+use chirp::debugger::{Breakpoint, Condition, Debugger};
+use chirp::disasm::{mnemonic, Syntax};
+use chirp::error::EmulatorError;
+use chirp::input::Input;
+use chirp::memory::Memory;
+use chirp::movie::Movie;
+use chirp::quirks::Quirks;
+use chirp::roms::get_rom;
+use chirp::scheduler::Scheduler;
+use chirp::timing::Timing;
 
+use crate::debugview::opcode_at;
+use crate::keyboard::Keyboard;
+use crate::movies::Movies;
+use crate::theme::{parse_colour, PixelStyle, Theme};
 use sapp_jsutils::JsObject;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Mutex;
 
 const CHIP_HZ: i32 = 500;
 pub static mut BLOOM: bool = false;
+pub static mut MUTED: bool = false;
+pub static mut VOLUME: f32 = 0.25;
+pub static mut PITCH: f32 = 440.0;
+pub static mut SPEED: f64 = 1.0;
+
+// keys held on the page's on-screen keypad, bit n is key n
+static WEB_KEYS: AtomicU16 = AtomicU16::new(0);
+
+// the part of memory the page's hex view shows
+static MEMORY_VIEW: Mutex<Range<usize>> = Mutex::new(0..256);
 
 lazy_static! {
     pub static ref CHIP: Mutex<Chip8> = Mutex::new(Chip8::init(CHIP_HZ));
+    pub static ref DEBUGGER: Mutex<Debugger> = Mutex::new(Debugger::new());
+    pub static ref MOVIES: Mutex<Movies> = Mutex::new(Movies::Off);
+    pub static ref THEME: Mutex<Theme> = Mutex::new(Theme::new());
+    static ref ROM: Mutex<Vec<u8>> = Mutex::new(Vec::new()); // the running ROM, for movies
 }
 
 extern "C" {
     pub fn draw_memory(js_object: JsObject);
+    pub fn memory_regions(js_object: JsObject);
     pub fn draw_registers(js_object: JsObject);
-
+    pub fn draw_cpu(js_object: JsObject);
+    pub fn report_error(js_object: JsObject);
+    pub fn download_state(js_object: JsObject);
+    pub fn download_movie(js_object: JsObject);
+    pub fn load_display() -> JsObject;
+    pub fn save_display(js_object: JsObject);
+    pub fn tone_load(js_object: JsObject);
+    pub fn tone_play(js_object: JsObject);
+    pub fn tone_stop();
+    pub fn debug_status(js_object: JsObject);
 }
 
 #[no_mangle]
@@ -24,17 +65,210 @@ fn toggle_bloom() {
     unsafe { BLOOM = !BLOOM }
 }
 
+#[no_mangle]
+fn toggle_mute() {
+    unsafe { MUTED = !MUTED }
+}
+
+#[no_mangle]
+fn set_volume(js_object: JsObject) {
+    unsafe { VOLUME = js_object.field_f32("volume").clamp(0.0, 1.0) }
+}
+
+// {pitch: 440}, the beep's frequency in Hz
+#[no_mangle]
+fn set_pitch(js_object: JsObject) {
+    let pitch = js_object.field_f32("pitch");
+    if pitch.is_finite() && pitch > 0.0 {
+        unsafe { PITCH = pitch }
+    }
+}
+
+// {speed: 0.5}, 1 is real time
+#[no_mangle]
+fn set_speed(js_object: JsObject) {
+    let speed = js_object.field_f32("speed") as f64;
+    if speed.is_finite() {
+        unsafe { SPEED = speed.clamp(Scheduler::MIN_SPEED, Scheduler::MAX_SPEED) }
+    }
+}
+
+// the display settings the page saved last time, as `Theme::settings` text
+pub fn restore_theme() {
+    let mut settings = String::new();
+    unsafe { load_display() }.to_string(&mut settings);
+    *THEME.lock().unwrap() = Theme::from_settings(&settings);
+}
+
+fn change_theme(change: impl FnOnce(&mut Theme)) {
+    let mut theme = THEME.lock().unwrap();
+    change(&mut theme);
+    unsafe { save_display(JsObject::string(&theme.settings())) }
+}
+
+// one of `Theme::PALETTES` by name
+#[no_mangle]
+fn set_palette(js_object: JsObject) {
+    let mut name = String::new();
+    js_object.to_string(&mut name);
+
+    match Theme::palette(&name) {
+        Some(palette) => change_theme(|theme| {
+            theme.foreground = palette.foreground;
+            theme.background = palette.background;
+        }),
+        None => report_message(&format!("unknown palette {}", name)),
+    }
+}
+
+// {fg: "33FF66", bg: "001108"}, either may be empty
+#[no_mangle]
+fn set_colours(js_object: JsObject) {
+    let (mut fg, mut bg) = (String::new(), String::new());
+    js_object.field("fg").to_string(&mut fg);
+    js_object.field("bg").to_string(&mut bg);
+
+    let parse = |s: &str| (!s.is_empty()).then(|| parse_colour(s)).transpose();
+    match (parse(&fg), parse(&bg)) {
+        (Ok(fg), Ok(bg)) => change_theme(|theme| {
+            theme.foreground = fg.unwrap_or(theme.foreground);
+            theme.background = bg.unwrap_or(theme.background);
+        }),
+        (Err(e), _) | (_, Err(e)) => report_message(&e),
+    }
+}
+
+#[no_mangle]
+fn set_pixels(js_object: JsObject) {
+    let mut name = String::new();
+    js_object.to_string(&mut name);
+
+    if let Some(style) = PixelStyle::from_name(&name) {
+        change_theme(|theme| theme.style = style);
+    }
+}
+
 #[no_mangle]
 fn load_rom(js_object: JsObject) {
     let mut name = String::new();
     js_object.to_string(&mut name);
+    start_rom(get_rom(&name));
+}
 
-    let rom = get_rom(&name);
+// a ROM file picked or dropped on the page
+#[no_mangle]
+fn load_rom_bytes(js_object: JsObject) {
+    let mut rom = Vec::new();
+    js_object.to_byte_buffer(&mut rom);
+    start_rom(&rom);
+}
+
+pub fn start_rom(rom: &[u8]) {
     let mut chippy = CHIP.lock().unwrap();
+    match chippy.load_rom(rom) {
+        Ok(()) => {
+            *ROM.lock().unwrap() = rom.to_vec();
+            send_regions(rom.len());
+            MOVIES.lock().unwrap().stop();
+            live_input(&mut chippy);
+        }
+        Err(e) => report_message(&e.to_string()),
+    }
+}
 
-    chippy.reset();
-    chippy.load_font(0x00);
-    chippy.load(0x200, rom.to_vec());
+pub fn live_input(chippy: &mut Chip8) {
+    chippy.keypad.set_input((Keyboard, WebInput));
+}
+
+// start recording the running ROM from reset, or stop and download the movie
+#[no_mangle]
+fn movie_record() {
+    let mut chippy = CHIP.lock().unwrap();
+    let mut movies = MOVIES.lock().unwrap();
+    if let Movies::Recording(_) = *movies {
+        let movie = movies.stop().unwrap();
+        unsafe { download_movie(JsObject::buffer(&movie.to_bytes())) }
+        return;
+    }
+    live_input(&mut chippy);
+    match Movie::record(&mut chippy, &ROM.lock().unwrap()) {
+        Ok(movie) => *movies = Movies::Recording(movie),
+        Err(e) => report_message(&e.to_string()),
+    }
+}
+
+// play a movie of the running ROM
+#[no_mangle]
+fn movie_play(js_object: JsObject) {
+    let mut data = Vec::new();
+    js_object.to_byte_buffer(&mut data);
+
+    let mut chippy = CHIP.lock().unwrap();
+    let result = Movie::from_bytes(&data)
+        .and_then(|movie| movie.play(&mut chippy, &ROM.lock().unwrap()).map(|_| movie));
+    match result {
+        Ok(movie) => *MOVIES.lock().unwrap() = Movies::play(&movie),
+        Err(e) => report_message(&e.to_string()),
+    }
+}
+
+#[no_mangle]
+fn set_key(js_object: JsObject) {
+    let bit = 1 << (js_object.field_u32("key") & 0xF);
+    if js_object.field_u32("down") != 0 {
+        WEB_KEYS.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        WEB_KEYS.fetch_and(!bit, Ordering::Relaxed);
+    }
+}
+
+// input backend for keys pressed on the web page
+pub struct WebInput;
+
+impl Input for WebInput {
+    fn is_key_down(&self, key: u8) -> bool {
+        WEB_KEYS.load(Ordering::Relaxed).is_key_down(key)
+    }
+}
+
+#[no_mangle]
+fn set_quirks(js_object: JsObject) {
+    let mut name = String::new();
+    js_object.to_string(&mut name);
+
+    if let Some(quirks) = Quirks::from_name(&name) {
+        CHIP.lock().unwrap().quirks = quirks;
+    }
+}
+
+#[no_mangle]
+fn set_timing(js_object: JsObject) {
+    let mut name = String::new();
+    js_object.to_string(&mut name);
+
+    if let Some(timing) = Timing::from_name(&name) {
+        CHIP.lock().unwrap().timing = timing;
+    }
+}
+
+#[no_mangle]
+fn export_state() {
+    let state = CHIP.lock().unwrap().save_state();
+    unsafe { download_state(JsObject::buffer(&state)) }
+}
+
+#[no_mangle]
+fn import_state(js_object: JsObject) {
+    // a state would desync the movie, as on native
+    if MOVIES.lock().unwrap().active() {
+        return report_message("can't load a state while a movie is active");
+    }
+    let mut state = Vec::new();
+    js_object.to_byte_buffer(&mut state);
+
+    if let Err(e) = CHIP.lock().unwrap().load_state(&state) {
+        unsafe { report_error(JsObject::string(&e.to_string())) }
+    }
 }
 
 #[no_mangle]
@@ -43,12 +277,269 @@ fn update_hz(js_object: JsObject) {
     CHIP.lock().unwrap().set_hz(new_hz as i32);
 }
 
-impl Chip8 {
-    pub fn send_state(&mut self) {
+#[no_mangle]
+fn debug_pause() {
+    let chippy = CHIP.lock().unwrap();
+    DEBUGGER.lock().unwrap().toggle_pause(&chippy);
+}
+
+#[no_mangle]
+fn debug_step() {
+    let mut chippy = CHIP.lock().unwrap();
+    if let Err(e) = DEBUGGER.lock().unwrap().step_into(&mut chippy) {
+        show_error(&e);
+    }
+}
+
+#[no_mangle]
+fn debug_step_over() {
+    let mut chippy = CHIP.lock().unwrap();
+    if let Err(e) = DEBUGGER.lock().unwrap().step_over(&mut chippy) {
+        show_error(&e);
+    }
+}
+
+#[no_mangle]
+fn debug_step_out() {
+    let mut chippy = CHIP.lock().unwrap();
+    if let Err(e) = DEBUGGER.lock().unwrap().step_out(&mut chippy) {
+        show_error(&e);
+    }
+}
+
+// {addr: "0x2A4", condition: "v3 == 5"}, condition may be empty
+#[no_mangle]
+fn add_breakpoint(js_object: JsObject) {
+    let (mut addr, mut condition) = (String::new(), String::new());
+    js_object.field("addr").to_string(&mut addr);
+    js_object.field("condition").to_string(&mut condition);
+
+    let mut debugger = DEBUGGER.lock().unwrap();
+    let Some(addr) = debugger.resolve(addr.trim()) else {
+        return report_message(&format!("bad breakpoint address: {}", addr));
+    };
+    let bp = if condition.trim().is_empty() {
+        Breakpoint::new(addr)
+    } else {
+        match Condition::parse(&condition) {
+            Some(c) => Breakpoint::when(addr, c),
+            None => return report_message(&format!("bad condition: {}", condition)),
+        }
+    };
+    debugger.add_breakpoint(bp);
+}
+
+#[no_mangle]
+fn remove_breakpoint(js_object: JsObject) {
+    let mut addr = String::new();
+    js_object.to_string(&mut addr);
+    let mut debugger = DEBUGGER.lock().unwrap();
+    if let Some(addr) = debugger.resolve(addr.trim()) {
+        debugger.remove_breakpoint(addr);
+    }
+}
+
+// a symbol map written by the assembler, for breakpoints by label
+#[no_mangle]
+fn import_symbols(js_object: JsObject) {
+    let mut map = String::new();
+    js_object.to_string(&mut map);
+    DEBUGGER.lock().unwrap().symbols = parse_symbols(&map);
+}
+
+// {start: "0x300", end: "0x30F"}, end may be empty for a single byte
+#[no_mangle]
+fn add_watchpoint(js_object: JsObject) {
+    if let Some(range) = watch_range(&js_object) {
+        CHIP.lock().unwrap().memory.watch(range);
+    }
+}
+
+#[no_mangle]
+fn remove_watchpoint(js_object: JsObject) {
+    if let Some(range) = watch_range(&js_object) {
+        CHIP.lock().unwrap().memory.unwatch(&range);
+    }
+}
+
+fn watch_range(js_object: &JsObject) -> Option<std::ops::RangeInclusive<u16>> {
+    let (mut start, mut end) = (String::new(), String::new());
+    js_object.field("start").to_string(&mut start);
+    js_object.field("end").to_string(&mut end);
+
+    let debugger = DEBUGGER.lock().unwrap();
+    let Some(start) = debugger.resolve(start.trim()) else {
+        report_message(&format!("bad watchpoint address: {}", start));
+        return None;
+    };
+    let end = debugger.resolve(end.trim()).unwrap_or(start);
+    Some(start.min(end)..=start.max(end))
+}
+
+pub fn send_debug(debugger: &Debugger, chippy: &Chip8) {
+    let pc = match debugger.symbol_at(chippy.pc) {
+        Some(name) => format!("{:#05x} {}", chippy.pc, name),
+        None => format!("{:#05x}", chippy.pc),
+    };
+    let status = JsObject::object();
+    status.set_field_string(
+        "state",
+        &match (debugger.paused, debugger.stop) {
+            (false, _) => "running".to_owned(),
+            (true, Some(reason)) => format!("{} (pc {})", reason, pc),
+            (true, None) => format!("paused (pc {})", pc),
+        },
+    );
+    let breakpoints: Vec<String> = debugger
+        .breakpoints()
+        .iter()
+        .map(|bp| match bp.condition {
+            Some(c) => format!("{:#05x} if {}", bp.addr, c),
+            None => format!("{:#05x}", bp.addr),
+        })
+        .collect();
+    status.set_field_string("breakpoints", &breakpoints.join("\n"));
+    let watches: Vec<String> = chippy
+        .memory
+        .watches()
+        .iter()
+        .map(|r| format!("{:#05x}-{:#05x}", r.start(), r.end()))
+        .collect();
+    status.set_field_string("watchpoints", &watches.join("\n"));
+    unsafe { debug_status(status) }
+}
+
+// {start: 0x200, len: 512}, set as the page's hex view scrolls
+#[no_mangle]
+fn memory_view(js_object: JsObject) {
+    let start = (js_object.field_u32("start") as usize).min(Memory::SIZE - 1);
+    let len = (js_object.field_u32("len") as usize).min(Memory::SIZE - start);
+    *MEMORY_VIEW.lock().unwrap() = start..start + len;
+}
+
+// {addr: 0x300, value: 0xFF}, only while paused so the edit can't race the program
+#[no_mangle]
+fn poke_memory(js_object: JsObject) {
+    let mut chippy = CHIP.lock().unwrap();
+    if !DEBUGGER.lock().unwrap().paused {
+        return report_message("pause the debugger to edit memory");
+    }
+    let addr = js_object.field_u32("addr") as usize % Memory::SIZE;
+    chippy.memory.bytes_mut()[addr] = js_object.field_u32("value") as u8;
+    chippy.memory.heatmap[addr] = 255;
+}
+
+// one "start end name" line per region, addresses in hex
+fn send_regions(rom_len: usize) {
+    let regions: Vec<String> = Chip8::memory_regions(rom_len)
+        .iter()
+        .map(|(name, r)| format!("{:04x} {:04x} {}", r.start(), r.end(), name))
+        .collect();
+    unsafe { memory_regions(JsObject::string(&regions.join("\n"))) }
+}
+
+pub fn send_state(chippy: &mut Chip8) {
+    chippy.memory.tick(); // for visual heatmap
+
+    // the viewed memory then its heat, after the start address (u16 big endian)
+    let view = MEMORY_VIEW.lock().unwrap().clone();
+    let mut data = (view.start as u16).to_be_bytes().to_vec();
+    data.extend(&chippy.memory.bytes()[view.clone()]);
+    data.extend(&chippy.memory.heatmap[view]);
+    unsafe {
+        draw_memory(JsObject::buffer(&data));
+        draw_registers(JsObject::buffer(&chippy.v));
+        draw_cpu(cpu_state(chippy));
+    }
+}
+
+// everything but the v registers, numbers are sent as floats
+fn cpu_state(chippy: &Chip8) -> JsObject {
+    let opcode = opcode_at(chippy, chippy.pc);
+    let long = opcode_at(chippy, chippy.pc.wrapping_add(2));
+    let text = |syntax| mnemonic(opcode, long, syntax).unwrap_or_else(|| "unknown".to_owned());
+    let stack: Vec<String> = chippy
+        .stack
+        .slots()
+        .iter()
+        .map(|addr| format!("{:04X}", addr))
+        .collect();
+
+    let cpu = JsObject::object();
+    cpu.set_field_f32("pc", chippy.pc as f32);
+    cpu.set_field_f32("opcode", opcode as f32);
+    cpu.set_field_string("octo", &text(Syntax::Octo));
+    cpu.set_field_string("classic", &text(Syntax::Classic));
+    cpu.set_field_f32("i", chippy.i as f32);
+    cpu.set_field_f32("delay", chippy.delay_timer as f32);
+    cpu.set_field_f32("sound", chippy.sound_timer as f32);
+    cpu.set_field_string("stack", &stack.join(" "));
+    cpu.set_field_f32("sp", chippy.stack.depth() as f32);
+    // the register FX0A stores the key in, -1 when not waiting
+    let waiting = if chippy.keypad.waiting {
+        chippy.keypad.dest_register as f32
+    } else {
+        -1.0
+    };
+    cpu.set_field_f32("waiting", waiting);
+    cpu.set_field_f32("halted", chippy.halted as u8 as f32);
+    cpu
+}
+
+pub fn show_error(e: &EmulatorError) {
+    report_message(&e.to_string());
+}
+
+pub fn report_message(message: &str) {
+    unsafe { report_error(JsObject::string(message)) }
+}
+
+// plays the sound timer through the page's WebAudio context
+pub struct WebSpeaker {
+    beeper: Beeper,
+    loaded: Option<Waveform>, // waveform the page has decoded
+    playing: bool,
+    volume: f32, // volume last sent to the page
+}
+
+impl WebSpeaker {
+    pub fn new() -> Self {
+        Self {
+            beeper: Beeper::new(),
+            loaded: None,
+            playing: false,
+            volume: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, chippy: &Chip8) {
         unsafe {
-            self.memory.tick(); // for visual heatmap
-            draw_memory(JsObject::buffer(&self.memory.heatmap));
-            draw_registers(JsObject::buffer(&self.v));
+            self.beeper.muted = MUTED;
+            self.beeper.volume = VOLUME;
+            self.beeper.pitch = PITCH;
+        }
+
+        if !self.beeper.active(chippy) {
+            if self.playing {
+                unsafe { tone_stop() }
+                self.playing = false;
+            }
+            return;
+        }
+
+        let waveform = self.beeper.waveform(chippy);
+        if self.loaded != Some(waveform) {
+            unsafe { tone_load(JsObject::buffer(&waveform.wav())) }
+            self.loaded = Some(waveform);
+            self.playing = false;
+        }
+
+        if !self.playing || self.volume != self.beeper.volume {
+            self.volume = self.beeper.volume;
+            let params = JsObject::object();
+            params.set_field_f32("volume", self.volume);
+            unsafe { tone_play(params) }
+            self.playing = true;
         }
     }
 }
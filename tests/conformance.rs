@@ -0,0 +1,88 @@
+// Runs the bundled test ROMs headlessly and compares the screen they leave
+// against the golden snapshots in tests/golden.
+//
+// Snapshots are `Snapshot` ASCII art, see `chirp::snapshot`. After an
+// intentional change, regenerate them with
+// `CHIRP_BLESS=1 cargo test --test conformance`.
+use std::{env, fs, path::PathBuf};
+
+use chirp::{
+    chip8::Chip8,
+    input::Scripted,
+    snapshot::{Diff, Snapshot},
+};
+
+const HZ: i32 = 1000;
+
+fn run(rom: &str, frames: usize, input: Option<Scripted>) -> Chip8 {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/roms")
+        .join(rom);
+    let data = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    let mut chippy = Chip8::init(HZ);
+    chippy.load_rom(&data).unwrap();
+    if let Some(input) = input {
+        chippy.keypad.set_input(input);
+    }
+    for _ in 0..frames {
+        chippy.run_frame().unwrap();
+    }
+    chippy
+}
+
+fn check(name: &str, chippy: &Chip8) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", name));
+    let actual = Snapshot::from_display(&chippy.display);
+
+    if env::var_os("CHIRP_BLESS").is_some() {
+        fs::write(&path, actual.to_ascii()).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with CHIRP_BLESS=1)", path.display(), e));
+    let expected = Snapshot::from_ascii(&expected).unwrap();
+    match actual.diff(&expected) {
+        Diff::Same => {}
+        diff => panic!("{} doesn't match {}: {}", name, path.display(), diff),
+    }
+    // nothing outside the visible screen
+    let visible = chippy.display.width() as usize * chippy.display.height() as usize;
+    assert!(chippy.display.buffer[visible..].iter().all(|&p| p == 0));
+}
+
+#[test]
+fn test_opcode() {
+    let chippy = run("test_opcode.ch8", 120, None);
+    assert!(chippy.error.is_none());
+    check("test_opcode", &chippy);
+}
+
+#[test]
+fn bc_test() {
+    let chippy = run("bc_test.ch8", 120, None);
+    assert!(chippy.error.is_none());
+    check("bc_test", &chippy);
+}
+
+#[test]
+fn c8_test() {
+    let chippy = run("c8_test.ch8", 120, None);
+    assert!(chippy.error.is_none());
+    check("c8_test", &chippy);
+}
+
+#[test]
+fn keypad_test() {
+    // choose the first test from the menu, then hold 5
+    let input = Scripted::new()
+        .wait(10)
+        .hold(&[0x1], 10)
+        .wait(10)
+        .hold(&[0x5], 60);
+    let chippy = run("keypad_test.ch8", 80, Some(input));
+    assert!(chippy.error.is_none());
+    check("keypad_test", &chippy);
+}